@@ -1,12 +1,43 @@
 use config::Config;
 use lazy_static::lazy_static;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::engine::security::SecurityClass;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub kafka: KafkaSettings,
+    #[serde(default)]
     pub proxy: ProxySettings,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    #[serde(default)]
+    pub archive: ArchiveSettings,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerSettings,
+    #[serde(default)]
+    pub decoding: DecodingSettings,
+    #[serde(default)]
+    pub cache: CacheSettings,
+    #[serde(default)]
+    pub headers: HeaderSettings,
+    #[serde(default)]
+    pub sessions: SessionSettings,
+    #[serde(default)]
+    pub retry: RetrySettings,
+    #[serde(default)]
+    pub parsing: ParsingSettings,
+    #[serde(default)]
+    pub schema: SchemaSettings,
+    #[serde(default)]
+    pub securities: SecuritySettings,
+    #[serde(default)]
+    pub sources: SourceSettings,
+    #[serde(default)]
+    pub datasets: DatasetSettings,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -26,13 +57,215 @@ pub struct KafkaSettings {
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ProxySettings {
+    #[serde(default)]
+    pub servers: Vec<ProxyServer>,
+    #[serde(default)]
+    pub selection: ProxySelection,
+    #[serde(default = "ProxySettings::default_cooldown_secs")]
+    pub cooldown_secs: u64,
+    #[serde(default)]
+    pub no_proxy_hosts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProxyServer {
+    /// http, https, socks5 or socks5h
+    #[serde(default = "ProxyServer::default_scheme")]
+    pub scheme: String,
     pub host: String,
     pub port: String,
+    #[serde(default)]
     pub username: String,
+    /// Environment variable holding the password of this server
+    #[serde(default = "ProxyServer::default_passwd_env")]
+    pub passwd_env: String,
     #[serde(skip_deserializing)]
     pub passwd: String,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxySelection {
+    #[default]
+    RoundRobin,
+    LeastFailures,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RateLimitSettings {
+    #[serde(default)]
+    pub default: HostRateLimit,
+    #[serde(default)]
+    pub hosts: HashMap<String, HostRateLimit>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HostRateLimit {
+    pub requests_per_second: f64,
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArchiveSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "ArchiveSettings::default_path")]
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CircuitBreakerSettings {
+    pub failure_threshold: u32,
+    pub cooldown_secs: u64,
+    /// How long a probe may go without an outcome before another one is let through
+    #[serde(default = "CircuitBreakerSettings::default_probe_timeout_secs")]
+    pub probe_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DecodingSettings {
+    /// Replace malformed byte sequences instead of rejecting the payload
+    #[serde(default)]
+    pub lossy: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ParsingSettings {
+    /// Parse CSV exports with fixed column positions when no header row is found
+    #[serde(default)]
+    pub positional_fallback: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SourceSettings {
+    /// Format requested from TWSE per target, TPEx is always fetched as CSV
+    #[serde(default)]
+    pub twse: HashMap<String, SourceFormat>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatasetSettings {
+    /// Directory of the declarative dataset specs, one `<target>.yaml` each
+    #[serde(default = "DatasetSettings::default_path")]
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SecuritySettings {
+    /// Security classes ingested by targets without an allow-list of their own
+    #[serde(default = "SecuritySettings::default_classes")]
+    pub default: Vec<SecurityClass>,
+    /// Per-target allow-lists, keyed by target name
+    #[serde(default)]
+    pub targets: HashMap<String, Vec<SecurityClass>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SchemaSettings {
+    /// Compare upstream layouts against their expected fingerprints
+    #[serde(default = "SchemaSettings::default_enabled")]
+    pub enabled: bool,
+    /// Directory of the expected fingerprints, one JSON file per layout
+    #[serde(default = "SchemaSettings::default_path")]
+    pub path: String,
+    /// Record layouts lacking a fingerprint instead of failing on them
+    #[serde(default)]
+    pub record: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "CacheSettings::default_path")]
+    pub path: String,
+    /// Seconds a response is served without revalidation, 0 always revalidates
+    #[serde(default)]
+    pub ttl_secs: u64,
+    /// Per-host overrides of `ttl_secs`
+    #[serde(default)]
+    pub hosts: HashMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HeaderSettings {
+    #[serde(default)]
+    pub default: HeaderProfile,
+    /// Per-host profiles, unset fields fall back to `default`
+    #[serde(default)]
+    pub hosts: HashMap<String, HeaderProfile>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HeaderProfile {
+    /// Rotated request after request
+    #[serde(default)]
+    pub user_agents: Vec<String>,
+    pub referer: Option<String>,
+    pub accept_language: Option<String>,
+    /// Any other header sent as is
+    #[serde(default)]
+    pub extra: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SessionSettings {
+    /// Hosts only answering with the cookies of a landing page
+    #[serde(default)]
+    pub hosts: HashMap<String, SessionProfile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionProfile {
+    /// Page visited first to collect the session cookies
+    pub warm_up_url: String,
+    /// Seconds before the session is considered expired and warmed up again
+    #[serde(default = "SessionProfile::default_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RetrySettings {
+    #[serde(default)]
+    pub default: RetryPolicy,
+    /// Per source host policies, replacing `default` as a whole
+    #[serde(default)]
+    pub hosts: HashMap<String, RetryPolicy>,
+    /// Per sink policies, e.g. `kafka`, falling back on `RetryPolicy::sink`
+    /// rather than `default`, down to the fields they leave out
+    #[serde(default, deserialize_with = "RetrySettings::deserialize_sinks")]
+    pub sinks: HashMap<String, RetryPolicy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    #[serde(default = "RetryPolicy::default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Longest wait between two reads of the response
+    #[serde(default = "RetryPolicy::default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    /// Whole attempt, from connecting to the end of the body
+    #[serde(default = "RetryPolicy::default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "RetryPolicy::default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "RetryPolicy::default_min_backoff_ms")]
+    pub min_backoff_ms: u64,
+    #[serde(default = "RetryPolicy::default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// HTTP statuses retried on top of connection errors and timeouts
+    #[serde(default = "RetryPolicy::default_retryable_statuses")]
+    pub retryable_statuses: Vec<u16>,
+}
+
 lazy_static! {
     pub static ref SETTINGS: Settings = Settings::new();
 }
@@ -65,8 +298,10 @@ impl Settings {
             cfg.database.dbpasswd = db_password;
         }
 
-        if let Ok(proxy_password) = std::env::var("PROXY_PASSWD") {
-            cfg.proxy.passwd = proxy_password;
+        for server in cfg.proxy.servers.iter_mut() {
+            if let Ok(proxy_password) = std::env::var(&server.passwd_env) {
+                server.passwd = proxy_password;
+            }
         }
     }
 }
@@ -81,11 +316,45 @@ impl DatabaseSettings {
 }
 
 impl ProxySettings {
+    fn default_cooldown_secs() -> u64 {
+        300
+    }
+}
+
+impl Default for ProxySettings {
+    fn default() -> Self {
+        Self {
+            servers: Vec::new(),
+            selection: ProxySelection::default(),
+            cooldown_secs: Self::default_cooldown_secs(),
+            no_proxy_hosts: Vec::new(),
+        }
+    }
+}
+
+impl ProxyServer {
+    fn default_scheme() -> String {
+        "http".to_string()
+    }
+
+    fn default_passwd_env() -> String {
+        "PROXY_PASSWD".to_string()
+    }
+
     pub fn connection_string(&self) -> String {
-        format!(
-            "http://{}:{}@{}:{}",
-            self.username, self.passwd, self.host, self.port
-        )
+        if self.username.is_empty() {
+            format!("{}://{}:{}", self.scheme, self.host, self.port)
+        } else {
+            format!(
+                "{}://{}:{}@{}:{}",
+                self.scheme, self.username, self.passwd, self.host, self.port
+            )
+        }
+    }
+
+    /// Identify the server in logs without leaking credentials
+    pub fn name(&self) -> String {
+        format!("{}://{}:{}", self.scheme, self.host, self.port)
     }
 }
 
@@ -94,3 +363,273 @@ impl KafkaSettings {
         self.brokers.clone()
     }
 }
+
+impl RateLimitSettings {
+    pub fn for_host(&self, host: &str) -> &HostRateLimit {
+        self.hosts.get(host).unwrap_or(&self.default)
+    }
+}
+
+impl HeaderSettings {
+    /// Profile of a host merged over the default one
+    pub fn for_host(&self, host: &str) -> HeaderProfile {
+        let Some(profile) = self.hosts.get(host) else {
+            return self.default.clone();
+        };
+
+        let mut extra = self.default.extra.clone();
+        extra.extend(profile.extra.clone());
+        HeaderProfile {
+            user_agents: if profile.user_agents.is_empty() {
+                self.default.user_agents.clone()
+            } else {
+                profile.user_agents.clone()
+            },
+            referer: profile
+                .referer
+                .clone()
+                .or_else(|| self.default.referer.clone()),
+            accept_language: profile
+                .accept_language
+                .clone()
+                .or_else(|| self.default.accept_language.clone()),
+            extra,
+        }
+    }
+}
+
+impl SessionProfile {
+    fn default_ttl_secs() -> u64 {
+        1800
+    }
+}
+
+impl RetrySettings {
+    pub fn for_host(&self, host: &str) -> &RetryPolicy {
+        self.hosts.get(host).unwrap_or(&self.default)
+    }
+
+    pub fn for_sink(&self, sink: &str) -> RetryPolicy {
+        self.sinks
+            .get(sink)
+            .cloned()
+            .unwrap_or_else(RetryPolicy::sink)
+    }
+
+    fn deserialize_sinks<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<String, RetryPolicy>, D::Error> {
+        let sinks = HashMap::<String, serde_json::Map<String, serde_json::Value>>::deserialize(
+            deserializer,
+        )?;
+        sinks
+            .into_iter()
+            .map(|(sink, fields)| {
+                let mut policy =
+                    serde_json::to_value(RetryPolicy::sink()).map_err(serde::de::Error::custom)?;
+                if let serde_json::Value::Object(policy) = &mut policy {
+                    policy.extend(fields);
+                }
+                serde_json::from_value(policy)
+                    .map(|policy| (sink, policy))
+                    .map_err(serde::de::Error::custom)
+            })
+            .collect()
+    }
+}
+
+impl RetryPolicy {
+    /// Policy of sinks without one of their own: 3 attempts a second apart,
+    /// each given 5s to be delivered
+    pub fn sink() -> Self {
+        Self {
+            connect_timeout_secs: 30,
+            timeout_secs: 5,
+            max_retries: 2,
+            min_backoff_ms: 1000,
+            max_backoff_ms: 1000,
+            ..Self::default()
+        }
+    }
+
+    fn default_connect_timeout_secs() -> u64 {
+        10
+    }
+
+    fn default_read_timeout_secs() -> u64 {
+        30
+    }
+
+    fn default_timeout_secs() -> u64 {
+        60
+    }
+
+    fn default_max_retries() -> u32 {
+        3
+    }
+
+    fn default_min_backoff_ms() -> u64 {
+        1000
+    }
+
+    fn default_max_backoff_ms() -> u64 {
+        30_000
+    }
+
+    fn default_retryable_statuses() -> Vec<u16> {
+        vec![408, 429, 500, 502, 503, 504]
+    }
+
+    /// Wait before the given retry, doubling from `min_backoff_ms` up to
+    /// `max_backoff_ms`
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u64.saturating_pow(retry.saturating_sub(1));
+        Duration::from_millis(
+            self.min_backoff_ms
+                .saturating_mul(factor)
+                .min(self.max_backoff_ms.max(self.min_backoff_ms)),
+        )
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: Self::default_connect_timeout_secs(),
+            read_timeout_secs: Self::default_read_timeout_secs(),
+            timeout_secs: Self::default_timeout_secs(),
+            max_retries: Self::default_max_retries(),
+            min_backoff_ms: Self::default_min_backoff_ms(),
+            max_backoff_ms: Self::default_max_backoff_ms(),
+            retryable_statuses: Self::default_retryable_statuses(),
+        }
+    }
+}
+
+impl Default for HostRateLimit {
+    fn default() -> Self {
+        // roughly the pace of the former "sleep 1s every 25 urls" throttling
+        Self {
+            requests_per_second: 25.0,
+            burst: 25,
+        }
+    }
+}
+
+impl ArchiveSettings {
+    fn default_path() -> String {
+        "archive".to_string()
+    }
+}
+
+impl Default for ArchiveSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: Self::default_path(),
+        }
+    }
+}
+
+impl CircuitBreakerSettings {
+    fn default_probe_timeout_secs() -> u64 {
+        120
+    }
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown_secs: 60,
+            probe_timeout_secs: Self::default_probe_timeout_secs(),
+        }
+    }
+}
+
+impl CacheSettings {
+    fn default_path() -> String {
+        "cache".to_string()
+    }
+
+    pub fn ttl_for(&self, host: &str) -> u64 {
+        self.hosts.get(host).copied().unwrap_or(self.ttl_secs)
+    }
+}
+
+impl SecuritySettings {
+    fn default_classes() -> Vec<SecurityClass> {
+        vec![
+            SecurityClass::CommonStock,
+            SecurityClass::Preferred,
+            SecurityClass::Etf,
+            SecurityClass::BondEtf,
+            SecurityClass::Tdr,
+            SecurityClass::Etn,
+        ]
+    }
+
+    pub fn allowed(&self, target: &str) -> &[SecurityClass] {
+        self.targets.get(target).unwrap_or(&self.default)
+    }
+}
+
+impl SourceSettings {
+    pub fn twse_format(&self, target: &str) -> SourceFormat {
+        self.twse.get(target).copied().unwrap_or_default()
+    }
+}
+
+impl DatasetSettings {
+    fn default_path() -> String {
+        "datasets".to_string()
+    }
+}
+
+impl Default for DatasetSettings {
+    fn default() -> Self {
+        Self {
+            path: Self::default_path(),
+        }
+    }
+}
+
+impl Default for SecuritySettings {
+    fn default() -> Self {
+        Self {
+            default: Self::default_classes(),
+            targets: HashMap::new(),
+        }
+    }
+}
+
+impl SchemaSettings {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn default_path() -> String {
+        "schemas".to_string()
+    }
+}
+
+impl Default for SchemaSettings {
+    fn default() -> Self {
+        Self {
+            enabled: Self::default_enabled(),
+            path: Self::default_path(),
+            record: false,
+        }
+    }
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: Self::default_path(),
+            ttl_secs: 0,
+            hosts: HashMap::new(),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use csv::StringRecord;
+use std::collections::HashMap;
+
+/// Column of a CSV export found by its header name. `names` lists the
+/// canonical name first, then the aliases used by other exchanges or by
+/// previous layouts.
+pub struct Column {
+    pub key: &'static str,
+    pub names: &'static [&'static str],
+    pub required: bool,
+}
+
+/// Header cell without padding, BOM or full-width parentheses
+pub fn normalize(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '\u{feff}')
+        .map(|c| match c {
+            '（' => '(',
+            '）' => ')',
+            _ => c,
+        })
+        .collect()
+}
+
+/// Whether `record` names the first of `columns`, i.e. is meant as their
+/// header row even if other columns went missing
+pub fn names_first(record: &StringRecord, columns: &[Column]) -> bool {
+    columns.first().is_some_and(|column| {
+        record
+            .iter()
+            .map(normalize)
+            .any(|header| column.names.iter().any(|name| header == normalize(name)))
+    })
+}
+
+/// Positions of `columns` by key when `record` is their header row, i.e. when
+/// it names every required column
+pub fn locate(record: &StringRecord, columns: &[Column]) -> Option<HashMap<&'static str, usize>> {
+    let headers: Vec<String> = record.iter().map(normalize).collect();
+    let mut positions = HashMap::new();
+    for column in columns {
+        let position = column
+            .names
+            .iter()
+            .find_map(|name| headers.iter().position(|header| *header == normalize(name)));
+        match position {
+            Some(index) => {
+                positions.insert(column.key, index);
+            }
+            None if column.required => return None,
+            None => {}
+        }
+    }
+
+    Some(positions)
+}
+
+/// Positions of the columns of an export, mapped from its header row or fixed
+/// by exchange
+pub trait IndexSet: Sized {
+    /// Columns found by header name, the security code first
+    const COLUMNS: &'static [Column];
+
+    /// Build from the positions of `COLUMNS` by key
+    fn from_positions(positions: &HashMap<&'static str, usize>) -> Self;
+
+    /// Fixed positions of the TWSE export, only used as an explicit fallback
+    fn new_twse() -> Self;
+
+    /// Same for the TPEx export
+    fn new_tpex() -> Self;
+
+    /// Column of the security code
+    fn stock_id(&self) -> usize;
+
+    /// Columns a row needs values in to be parsed
+    fn values(&self) -> Vec<usize>;
+
+    /// Fields a record needs to hold every mapped column
+    fn width(&self) -> usize;
+
+    /// Whether `record` is the header row, found by the security code column
+    fn is_header(record: &StringRecord) -> bool {
+        names_first(record, Self::COLUMNS)
+    }
+
+    /// Map the columns by name when `record` is the header row
+    fn from_header(record: &StringRecord) -> Option<Self> {
+        locate(record, Self::COLUMNS).map(|positions| Self::from_positions(&positions))
+    }
+
+    /// Fixed positions of the exchange serving `source`
+    fn positional(source: &str) -> Option<Self> {
+        if source.contains("twse") {
+            Some(Self::new_twse())
+        } else if source.contains("tpex") {
+            Some(Self::new_tpex())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLUMNS: &[Column] = &[
+        Column {
+            key: "stock_id",
+            names: &["證券代號", "代號"],
+            required: true,
+        },
+        Column {
+            key: "turnover",
+            names: &["成交金額", "成交金額(元)"],
+            required: true,
+        },
+        Column {
+            key: "diff_sign",
+            names: &["漲跌(+/-)"],
+            required: false,
+        },
+    ];
+
+    #[test]
+    fn test_locate_by_alias() {
+        let header = StringRecord::from(vec!["代號", "名稱", " 成交金額（元）", "成交股數  "]);
+        let positions = locate(&header, COLUMNS).unwrap();
+        assert_eq!(positions["stock_id"], 0);
+        assert_eq!(positions["turnover"], 2);
+        assert!(!positions.contains_key("diff_sign"));
+        assert!(names_first(&header, COLUMNS));
+    }
+
+    #[test]
+    fn test_locate_rejects_data_rows() {
+        let row = StringRecord::from(vec!["2330", "台積電", "29,362,416,090"]);
+        assert!(locate(&row, COLUMNS).is_none());
+        assert!(!names_first(&row, COLUMNS));
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Local};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::path::Path;
+
+use crate::engine::fetcher::FetchRequest;
+
+/// Tabular dataset described in YAML, fetched, parsed and published without a
+/// model or strategy of its own
+#[derive(Debug, Clone, Deserialize)]
+pub struct Dataset {
+    /// Target name, also the archive directory and the schema layout
+    pub name: String,
+    /// Kafka topic the records are published to
+    pub topic: String,
+    pub sources: Vec<Source>,
+    #[serde(default)]
+    pub format: Format,
+    /// Selector of the data rows of an HTML page
+    #[serde(default)]
+    pub rows: Option<String>,
+    pub fields: Vec<Field>,
+}
+
+/// Endpoint of a dataset, `{date}` in its URL being replaced by the trading day
+#[derive(Debug, Clone, Deserialize)]
+pub struct Source {
+    pub url: String,
+    #[serde(default)]
+    pub date: DateFormat,
+    /// Put between year, month and day, e.g. `/` for TPEx
+    #[serde(default)]
+    pub separator: String,
+    /// Charset forced on the body, e.g. `big5` when it is not declared
+    #[serde(default)]
+    pub encoding: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateFormat {
+    #[default]
+    Gregorian,
+    /// Republic of China calendar, years counted from 1912
+    Roc,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    #[default]
+    Csv,
+    Html,
+}
+
+/// Value read from every row, by CSV header name or HTML selector
+#[derive(Debug, Clone, Deserialize)]
+pub struct Field {
+    /// Key of the value in the published record
+    pub key: String,
+    /// Header names of the CSV column, canonical name first then aliases
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Selector of the cell within an HTML row
+    #[serde(default)]
+    pub selector: Option<String>,
+    #[serde(default, rename = "type")]
+    pub kind: FieldType,
+    /// Rows missing the value are rejected, otherwise it is published as null
+    #[serde(default = "Field::default_required")]
+    pub required: bool,
+    #[serde(default, deserialize_with = "pattern")]
+    pub pattern: Option<Regex>,
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    #[default]
+    String,
+    Integer,
+    Float,
+    /// Security code, filtered by the allow-list of the dataset
+    Security,
+}
+
+impl Dataset {
+    /// Load the spec of `name` from `<root>/<name>.yaml`, `None` when absent
+    pub fn find(root: &Path, name: &str) -> Result<Option<Self>> {
+        let path = root.join(format!("{}.yaml", name));
+        let spec = match std::fs::read_to_string(&path) {
+            Ok(spec) => spec,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let dataset: Self = serde_yaml::from_str(&spec)
+            .map_err(|e| anyhow!("Invalid dataset {}: {}", path.display(), e))?;
+        dataset.validate()?;
+        Ok(Some(dataset))
+    }
+
+    /// Fail on specs the strategy could not follow
+    fn validate(&self) -> Result<()> {
+        if self.format == Format::Html && self.rows.is_none() {
+            return Err(anyhow!("Dataset {} reads HTML without rows", self.name));
+        }
+        for field in self.fields.iter() {
+            let located = match self.format {
+                Format::Csv => !field.columns.is_empty(),
+                Format::Html => field.selector.is_some(),
+            };
+            if !located {
+                return Err(anyhow!(
+                    "Field {} of dataset {} has no {}",
+                    field.key,
+                    self.name,
+                    match self.format {
+                        Format::Csv => "columns",
+                        Format::Html => "selector",
+                    }
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Source {
+    pub fn request(&self, day: DateTime<Local>) -> FetchRequest {
+        FetchRequest {
+            charset: self.encoding.clone(),
+            ..FetchRequest::get(self.url.replace("{date}", &self.format_date(day)))
+        }
+    }
+
+    fn format_date(&self, day: DateTime<Local>) -> String {
+        let year = match self.date {
+            DateFormat::Gregorian => day.year(),
+            DateFormat::Roc => day.year() - 1911,
+        };
+        format!(
+            "{}{sep}{:02}{sep}{:02}",
+            year,
+            day.month(),
+            day.day(),
+            sep = self.separator
+        )
+    }
+}
+
+impl Field {
+    fn default_required() -> bool {
+        true
+    }
+}
+
+fn pattern<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|pattern| Regex::new(&pattern).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_deserialize_spec() {
+        let dataset: Dataset = serde_yaml::from_str(
+            r#"
+name: foreign_holding
+topic: foreignholding-v1
+sources:
+  - url: "https://www.tpex.org.tw/holding?d={date}"
+    date: roc
+    separator: /
+    encoding: big5
+fields:
+  - key: stockId
+    columns: [證券代號, 代號]
+    type: security
+  - key: ratio
+    columns: [全體外資及陸資持股比率]
+    type: float
+    max: 100
+    required: false
+"#,
+        )
+        .unwrap();
+        dataset.validate().unwrap();
+        assert_eq!(dataset.format, Format::Csv);
+        assert_eq!(dataset.fields[0].kind, FieldType::Security);
+        assert!(dataset.fields[0].required);
+        assert_eq!(dataset.fields[1].max, Some(100.0));
+
+        let day = Local.with_ymd_and_hms(2024, 7, 23, 0, 0, 0).unwrap();
+        let request = dataset.sources[0].request(day);
+        assert_eq!(request.url, "https://www.tpex.org.tw/holding?d=113/07/23");
+        assert_eq!(request.charset.as_deref(), Some("big5"));
+    }
+
+    #[test]
+    fn test_find_shipped_specs() {
+        let root = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/datasets"));
+        let dataset = Dataset::find(root, "foreign_holding").unwrap().unwrap();
+        assert_eq!(dataset.topic, "foreignholding-v1");
+        assert!(Dataset::find(root, "unknown").unwrap().is_none());
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::fs;
+
+use super::{decode_documents, FetchError, FetchMeta, FetchRequest, Payload, PayloadStream};
+
+/// Metadata stored next to every archived body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub source: String,
+    pub content_type: String,
+    pub date: Option<String>,
+    pub fetched_at: String,
+    pub sha256: String,
+    #[serde(default)]
+    pub request: Option<FetchRequest>,
+    /// How the body was fetched in the first place
+    #[serde(default)]
+    pub meta: FetchMeta,
+}
+
+/// Content-addressed store of raw payloads, laid out as
+/// `<root>/<target>/<date>/<sha256>.body` with a `<sha256>.json` metadata file.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    root: PathBuf,
+}
+
+impl Archive {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Start archiving a body streamed chunk by chunk
+    pub fn writer(
+        &self,
+        target: &str,
+        date: &str,
+        stream: &PayloadStream,
+        fetched_at: DateTime<Local>,
+    ) -> Result<ArchiveWriter> {
+        let dir = self.root.join(target).join(date);
+        std::fs::create_dir_all(&dir)?;
+        // hidden, so neither replays nor directory sources pick it up
+        let partial = dir.join(format!(
+            ".{:x}.partial",
+            Sha256::digest(format!("{}{:?}", stream.source, fetched_at))
+        ));
+
+        Ok(ArchiveWriter {
+            file: BufWriter::new(std::fs::File::create(&partial)?),
+            partial,
+            dir,
+            hasher: Sha256::new(),
+            entry: ArchiveEntry {
+                source: stream.source.clone(),
+                content_type: stream.content_type.clone(),
+                date: stream.date.clone().or_else(|| Some(date.to_owned())),
+                fetched_at: fetched_at.to_rfc3339(),
+                sha256: String::new(),
+                request: stream.request.clone(),
+                meta: stream.meta(),
+            },
+            meta: stream.meta.clone(),
+        })
+    }
+}
+
+/// Archive entry written while its body streams through. Chunks arrive while
+/// the stream is polled, so it writes with blocking IO into a hidden partial
+/// file that only becomes `<sha256>.body` once the body is complete.
+pub struct ArchiveWriter {
+    dir: PathBuf,
+    partial: PathBuf,
+    file: BufWriter<std::fs::File>,
+    hasher: Sha256,
+    entry: ArchiveEntry,
+    /// Fetch metadata, only complete once the body streamed through
+    meta: Arc<Mutex<FetchMeta>>,
+}
+
+impl ArchiveWriter {
+    pub fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        self.hasher.update(chunk);
+        self.file.write_all(chunk)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<PathBuf> {
+        self.file.flush()?;
+        let sha256 = format!("{:x}", self.hasher.clone().finalize());
+        let body_path = self.dir.join(format!("{}.body", sha256));
+        std::fs::rename(&self.partial, &body_path)?;
+
+        self.entry.sha256 = sha256.clone();
+        self.entry.meta = self
+            .meta
+            .lock()
+            .expect("Fetch metadata lock poisoned")
+            .clone();
+        std::fs::write(
+            self.dir.join(format!("{}.json", sha256)),
+            serde_json::to_vec_pretty(&self.entry)?,
+        )?;
+        Ok(body_path)
+    }
+}
+
+impl Drop for ArchiveWriter {
+    fn drop(&mut self) {
+        // left over when the stream failed or was dropped half way
+        let _ = std::fs::remove_file(&self.partial);
+    }
+}
+
+impl ArchiveEntry {
+    /// Read the metadata sidecar written next to an archived body
+    pub async fn read(body_path: &Path) -> Result<Self> {
+        let meta = fs::read(body_path.with_extension("json")).await?;
+        Ok(serde_json::from_slice(&meta)?)
+    }
+
+    /// Payloads of the raw body archived with this entry, decoded as when it
+    /// was fetched
+    pub fn restore(&self, body: Vec<u8>) -> Result<Vec<Payload>, FetchError> {
+        let mut payloads =
+            decode_documents(&self.source, "", &self.content_type, body, &self.meta)?;
+        for payload in payloads.iter_mut() {
+            payload.source = self.source.clone();
+            payload.content_type = self.content_type.clone();
+            payload.date = self.date.clone();
+            payload.request = self.request.clone();
+        }
+        Ok(payloads)
+    }
+}
+
+/// Load every archived payload of one `<target>/<date>` directory, restoring the
+/// original source URL so strategies still see where the content came from.
+pub async fn load_dir(dir: &Path) -> Result<Vec<Payload>> {
+    let mut bodies = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "body") {
+            bodies.push(path);
+        }
+    }
+    bodies.sort();
+
+    let mut payloads = Vec::with_capacity(bodies.len());
+    for body_path in bodies {
+        let entry = ArchiveEntry::read(&body_path).await?;
+        payloads.extend(entry.restore(fs::read(&body_path).await?)?);
+    }
+
+    Ok(payloads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use encoding_rs::BIG5;
+    use futures::stream;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_archive_raw_body() {
+        let root = std::env::temp_dir().join(format!("ultron-archive-{}", std::process::id()));
+        let archive = Archive::new(&root);
+        let (body, _, _) = BIG5.encode("證券代號,證券名稱\n2330,台積電\n");
+        let raw = Bytes::copy_from_slice(&body);
+        let source = "https://www.twse.com.tw/exchangeReport/MI_INDEX";
+
+        let mut payload = PayloadStream::decode(
+            source,
+            "text/csv; charset=big5",
+            Bytes::new(),
+            Box::pin(stream::once(async move { Ok(raw) })),
+            FetchMeta {
+                status: Some(200),
+                retries: 1,
+                ..Default::default()
+            },
+            false,
+        );
+        payload.request = Some(FetchRequest::get(source));
+        let writer = archive
+            .writer("daily_close", "20240723", &payload, Local::now())
+            .unwrap();
+        let mut payload = payload.tee(super::super::copy_to(
+            source,
+            writer,
+            ArchiveWriter::write,
+            ArchiveWriter::finish,
+        ));
+        let mut content = String::new();
+        payload.read_to_string(&mut content).await.unwrap();
+        let meta = payload.meta();
+
+        // the body is kept as fetched and keyed by the hash of the fetch
+        let dir = root.join("daily_close").join("20240723");
+        let body_path = dir.join(format!("{}.body", meta.sha256));
+        assert_eq!(fs::read(&body_path).await.unwrap(), body.as_ref());
+
+        let entry = ArchiveEntry::read(&body_path).await.unwrap();
+        assert_eq!(entry.sha256, meta.sha256);
+        assert_eq!(entry.source, source);
+        assert_eq!(entry.date.as_deref(), Some("20240723"));
+
+        // and decoded again on replay
+        let payloads = load_dir(&dir).await.unwrap();
+        assert_eq!(payloads.len(), 1);
+        assert_eq!(payloads[0].source, source);
+        assert_eq!(payloads[0].content, content);
+        assert_eq!(payloads[0].request, payload.request);
+        assert_eq!(payloads[0].meta, meta);
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+}
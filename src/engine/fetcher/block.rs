@@ -0,0 +1,150 @@
+use async_trait::async_trait;
+use bytes::BytesMut;
+use encoding_rs::BIG5;
+use futures::StreamExt;
+use http::Extensions;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+
+use super::stream::SNIFF_LEN;
+
+/// Header marking a response rewritten by `BlockDetectionMiddleware`
+pub const BLOCKED_HEADER: &str = "x-ultron-blocked";
+
+// (host suffix, marker) of known throttling, blocking and maintenance pages
+const BLOCK_MARKERS: &[(&str, &str)] = &[
+    ("twse.com.tw", "很抱歉，您的連線已超過"),
+    ("twse.com.tw", "請求過於頻繁"),
+    ("twse.com.tw", "系統維護"),
+    ("tpex.org.tw", "查詢過於頻繁"),
+    ("tpex.org.tw", "系統維護"),
+    ("fbs.com.tw", "系統維護"),
+    ("fbs.com.tw", "Service Unavailable"),
+];
+
+/// Recognise block pages served with HTTP 200 from the head of their body,
+/// returning the reason
+pub fn detect(url: &reqwest::Url, body: &[u8]) -> Option<String> {
+    let host = url.host_str().unwrap_or_default();
+    let (big5_text, _, _) = BIG5.decode(body);
+    let utf8_text = String::from_utf8_lossy(body);
+
+    for (suffix, marker) in BLOCK_MARKERS {
+        if host.ends_with(suffix) && (big5_text.contains(marker) || utf8_text.contains(marker)) {
+            return Some(format!("page contains \"{}\"", marker));
+        }
+    }
+
+    // a CSV export answered with an HTML page is never real data
+    let query = url.query().unwrap_or_default();
+    let csv_requested = query.contains("response=csv") || query.contains("o=csv");
+    let html = utf8_text
+        .trim_start()
+        .get(..5)
+        .is_some_and(|start| start.eq_ignore_ascii_case("<html") || start.starts_with("<!"));
+    if csv_requested && html {
+        return Some("HTML page served instead of CSV".to_string());
+    }
+
+    None
+}
+
+/// Turns block pages into 429 responses so the retry middleware backs off and
+/// the proxy pool and circuit breaker count them as failures.
+pub struct BlockDetectionMiddleware;
+
+#[async_trait]
+impl Middleware for BlockDetectionMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let url = req.url().clone();
+        let mut resp = next.run(req, extensions).await?;
+        if resp.status() != StatusCode::OK {
+            return Ok(resp);
+        }
+
+        // block pages are short, only the head of large bodies is looked at
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let mut head = BytesMut::new();
+        let mut complete = false;
+        while head.len() < SNIFF_LEN {
+            match resp.chunk().await? {
+                Some(chunk) => head.extend_from_slice(&chunk),
+                None => {
+                    complete = true;
+                    break;
+                }
+            }
+        }
+        let head = head.freeze();
+        let reason = detect(&url, &head);
+        let body = if complete {
+            reqwest::Body::from(head)
+        } else {
+            let head = futures::stream::once(async move { Ok(head) });
+            reqwest::Body::wrap_stream(head.chain(resp.bytes_stream()))
+        };
+
+        let mut builder = http::Response::builder();
+        for (name, value) in headers.iter() {
+            builder = builder.header(name, value);
+        }
+        let builder = match reason {
+            Some(reason) => {
+                eprintln!("Detected block page from {}: {}", url, reason);
+                builder
+                    .status(StatusCode::TOO_MANY_REQUESTS)
+                    .header(BLOCKED_HEADER, reason)
+            }
+            None => builder.status(status),
+        };
+
+        let rebuilt = builder
+            .body(body)
+            .map_err(|e| reqwest_middleware::Error::Middleware(e.into()))?;
+        Ok(Response::from(rebuilt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> reqwest::Url {
+        reqwest::Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_detect_twse_throttle_page() {
+        let (body, _, _) = BIG5.encode("<html><body>很抱歉，您的連線已超過限制</body></html>");
+        let reason = detect(
+            &url("https://www.twse.com.tw/exchangeReport/MI_INDEX?response=csv&date=20240723"),
+            &body,
+        );
+        assert!(reason.unwrap().contains("很抱歉"));
+    }
+
+    #[test]
+    fn test_detect_html_instead_of_csv() {
+        let reason = detect(
+            &url("https://www.tpex.org.tw/web/stock/3insti/daily_trade/3itrade_hedge_result.php?o=csv"),
+            b"  <!DOCTYPE html><html></html>",
+        );
+        assert_eq!(reason.unwrap(), "HTML page served instead of CSV");
+    }
+
+    #[test]
+    fn test_detect_regular_content() {
+        let (body, _, _) = BIG5.encode("\"證券代號\",\"證券名稱\"\n\"2330\",\"台積電\"");
+        assert!(detect(
+            &url("https://www.twse.com.tw/exchangeReport/MI_INDEX?response=csv&date=20240723"),
+            &body,
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,291 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use reqwest::header::{HeaderMap, ETAG, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use tokio::fs;
+
+use crate::config::setting::CacheSettings;
+
+/// Metadata stored next to every cached response body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub content_type: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub stored_at: String,
+    #[serde(skip)]
+    pub body: Vec<u8>,
+}
+
+impl CachedResponse {
+    fn new(url: &str, content_type: &str, headers: &HeaderMap, now: DateTime<Local>) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        Self {
+            url: url.to_owned(),
+            content_type: content_type.to_owned(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            stored_at: now.to_rfc3339(),
+            body: Vec::new(),
+        }
+    }
+
+    fn stored_at(&self) -> Option<DateTime<Local>> {
+        DateTime::parse_from_rfc3339(&self.stored_at)
+            .ok()
+            .map(|stored_at| stored_at.with_timezone(&Local))
+    }
+}
+
+/// On-disk cache of successful responses keyed by URL, laid out as
+/// `<root>/<sha256 of url>.body` with a `.json` metadata file.
+pub struct ResponseCache {
+    root: PathBuf,
+    settings: CacheSettings,
+}
+
+impl ResponseCache {
+    pub fn new(settings: &CacheSettings) -> Self {
+        Self {
+            root: PathBuf::from(&settings.path),
+            settings: settings.clone(),
+        }
+    }
+
+    fn path_of(&self, url: &str) -> PathBuf {
+        self.root
+            .join(format!("{:x}", Sha256::digest(url.as_bytes())))
+            .with_extension("body")
+    }
+
+    /// Cached response of the URL, unreadable entries count as misses
+    pub async fn load(&self, url: &str) -> Option<CachedResponse> {
+        let body_path = self.path_of(url);
+        let meta = fs::read(body_path.with_extension("json")).await.ok()?;
+        let mut entry: CachedResponse = serde_json::from_slice(&meta).ok()?;
+        entry.body = fs::read(&body_path).await.ok()?;
+        Some(entry)
+    }
+
+    /// Responses stored after the trading date they are about never change, the
+    /// others are fresh for the TTL of their host
+    pub fn is_fresh(&self, entry: &CachedResponse, now: DateTime<Local>) -> bool {
+        let Ok(url) = reqwest::Url::parse(&entry.url) else {
+            return false;
+        };
+        let Some(stored_at) = entry.stored_at() else {
+            return false;
+        };
+        // a response stored on the trading day may predate its final figures
+        if trading_date(&url).is_some_and(|date| date < stored_at.date_naive()) {
+            return true;
+        }
+
+        let ttl = self.settings.ttl_for(url.host_str().unwrap_or_default());
+        (now - stored_at).num_seconds() < ttl as i64
+    }
+
+    pub async fn store(
+        &self,
+        url: &str,
+        content_type: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+        now: DateTime<Local>,
+    ) -> Result<()> {
+        let entry = CachedResponse::new(url, content_type, headers, now);
+        fs::create_dir_all(&self.root).await?;
+        let body_path = self.path_of(url);
+        fs::write(&body_path, body).await?;
+        fs::write(
+            body_path.with_extension("json"),
+            serde_json::to_vec_pretty(&entry)?,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Start caching a response whose body is streamed chunk by chunk
+    pub fn writer(
+        &self,
+        url: &str,
+        content_type: &str,
+        headers: &HeaderMap,
+        now: DateTime<Local>,
+    ) -> Result<CacheWriter> {
+        std::fs::create_dir_all(&self.root)?;
+        let body_path = self.path_of(url);
+        let partial = body_path.with_extension("partial");
+        Ok(CacheWriter {
+            file: BufWriter::new(std::fs::File::create(&partial)?),
+            partial,
+            body_path,
+            entry: CachedResponse::new(url, content_type, headers, now),
+        })
+    }
+
+    /// Restart the TTL of an entry the server confirmed as unchanged
+    pub async fn touch(&self, entry: &CachedResponse, now: DateTime<Local>) -> Result<()> {
+        let entry = CachedResponse {
+            stored_at: now.to_rfc3339(),
+            body: Vec::new(),
+            ..entry.clone()
+        };
+        fs::write(
+            self.path_of(&entry.url).with_extension("json"),
+            serde_json::to_vec_pretty(&entry)?,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// Cache entry written while its body streams through, with blocking IO into
+/// a partial file renamed once the body is complete
+pub struct CacheWriter {
+    body_path: PathBuf,
+    partial: PathBuf,
+    file: BufWriter<std::fs::File>,
+    entry: CachedResponse,
+}
+
+impl CacheWriter {
+    pub fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        self.file.write_all(chunk)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.file.flush()?;
+        std::fs::rename(&self.partial, &self.body_path)?;
+        std::fs::write(
+            self.body_path.with_extension("json"),
+            serde_json::to_vec_pretty(&self.entry)?,
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.partial);
+    }
+}
+
+/// Trading date requested by a URL: TWSE `date=YYYYMMDD` or TPEx `d=YYY/MM/DD`
+/// in the ROC calendar
+fn trading_date(url: &reqwest::Url) -> Option<NaiveDate> {
+    url.query_pairs()
+        .find_map(|(name, value)| match name.as_ref() {
+            "date" => NaiveDate::parse_from_str(&value, "%Y%m%d").ok(),
+            "d" => {
+                let mut parts = value.splitn(2, '/');
+                let roc_year: i32 = parts.next()?.parse().ok()?;
+                let date =
+                    NaiveDate::parse_from_str(&format!("2000/{}", parts.next()?), "%Y/%m/%d")
+                        .ok()?;
+                date.with_year(roc_year + 1911)
+            }
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use std::collections::HashMap;
+
+    fn cache(root: &str) -> ResponseCache {
+        ResponseCache::new(&CacheSettings {
+            enabled: true,
+            path: std::env::temp_dir()
+                .join(format!("{}-{}", root, std::process::id()))
+                .display()
+                .to_string(),
+            ttl_secs: 0,
+            hosts: HashMap::from([("fubon-ebrokerdj.fbs.com.tw".to_string(), 3600)]),
+        })
+    }
+
+    fn entry(url: &str, stored_at: DateTime<Local>) -> CachedResponse {
+        CachedResponse {
+            url: url.to_string(),
+            content_type: "text/csv".to_string(),
+            etag: None,
+            last_modified: None,
+            stored_at: stored_at.to_rfc3339(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_trading_date() {
+        let twse = reqwest::Url::parse(
+            "https://www.twse.com.tw/exchangeReport/MI_INDEX?response=csv&date=20240723&type=ALLBUT0999",
+        )
+        .unwrap();
+        let tpex = reqwest::Url::parse(
+            "https://www.tpex.org.tw/web/stock/3insti/daily_trade/3itrade_hedge_result.php?l=zh-tw&o=csv&d=113/07/23",
+        )
+        .unwrap();
+        let expected = NaiveDate::from_ymd_opt(2024, 7, 23);
+        assert_eq!(trading_date(&twse), expected);
+        assert_eq!(trading_date(&tpex), expected);
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        let cache = cache("ultron-cache-fresh");
+        let now = Local.with_ymd_and_hms(2024, 7, 24, 15, 0, 0).unwrap();
+        let hour_ago = now - Duration::hours(1);
+
+        // stored after the trading date is immutable, stored on it is revalidated
+        let past = "https://www.twse.com.tw/exchangeReport/MI_INDEX?response=csv&date=20240723";
+        let today = "https://www.twse.com.tw/exchangeReport/MI_INDEX?response=csv&date=20240724";
+        assert!(cache.is_fresh(&entry(past, hour_ago), now));
+        assert!(!cache.is_fresh(&entry(past, now - Duration::days(1)), now));
+        assert!(!cache.is_fresh(&entry(today, hour_ago), now));
+
+        let fubon = "https://fubon-ebrokerdj.fbs.com.tw/z/zc/zco/zco_2330_1.djhtm";
+        assert!(cache.is_fresh(&entry(fubon, now - Duration::minutes(30)), now));
+        assert!(!cache.is_fresh(&entry(fubon, now - Duration::hours(2)), now));
+    }
+
+    #[tokio::test]
+    async fn test_store_and_touch() {
+        let cache = cache("ultron-cache-store");
+        let url = "https://www.twse.com.tw/exchangeReport/MI_INDEX?response=csv&date=20240724";
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, "\"abc\"".parse().unwrap());
+        let stored_at = Local::now() - Duration::hours(1);
+
+        assert!(cache.load(url).await.is_none());
+        cache
+            .store(url, "text/csv", &headers, b"2330,TSMC", stored_at)
+            .await
+            .unwrap();
+
+        let entry = cache.load(url).await.unwrap();
+        assert_eq!(entry.body, b"2330,TSMC");
+        assert_eq!(entry.etag.as_deref(), Some("\"abc\""));
+        assert!(entry.last_modified.is_none());
+
+        let now = Local::now();
+        cache.touch(&entry, now).await.unwrap();
+        let touched = cache.load(url).await.unwrap();
+        assert_eq!(touched.stored_at(), Some(now));
+        assert_eq!(touched.body, b"2330,TSMC");
+
+        fs::remove_dir_all(&cache.root).await.unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use http::Extensions;
+use lazy_static::lazy_static;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+
+lazy_static! {
+    static ref PLAYBACK: RwLock<HashMap<String, Interaction>> = RwLock::new(HashMap::new());
+    static ref RECORDER: Mutex<Option<Vec<Interaction>>> = Mutex::new(None);
+}
+
+/// One recorded request/response pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub url: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// Base64 encoded so Big5 bodies survive byte-for-byte
+    pub body: String,
+}
+
+fn key(method: &str, url: &str) -> String {
+    format!("{} {}", method, url)
+}
+
+impl Interaction {
+    fn to_response(&self) -> Result<Response> {
+        let mut builder = http::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let body = BASE64.decode(&self.body)?;
+        Ok(Response::from(builder.body(body)?))
+    }
+}
+
+/// A cassette file of recorded interactions. Loading one registers its responses
+/// for playback until the cassette is dropped; with `ULTRON_RECORD` set, real
+/// responses are recorded instead and written to the file on drop.
+///
+/// Recording shares one global recorder, so record with `--test-threads=1`.
+#[cfg(all(test, feature = "testing"))]
+pub struct Cassette {
+    path: std::path::PathBuf,
+    recording: bool,
+    /// Playback keys registered by this cassette
+    keys: Vec<String>,
+}
+
+#[cfg(all(test, feature = "testing"))]
+impl Cassette {
+    pub fn insert(path: impl Into<std::path::PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let recording = std::env::var("ULTRON_RECORD").is_ok();
+        let mut keys = Vec::new();
+        if recording {
+            *RECORDER.lock().expect("Cassette recorder lock poisoned") = Some(Vec::new());
+        } else {
+            let content = std::fs::read_to_string(&path).map_err(|e| {
+                anyhow::anyhow!("Failed to read cassette {}: {}", path.display(), e)
+            })?;
+            let interactions: Vec<Interaction> = serde_json::from_str(&content)?;
+            let mut playback = PLAYBACK.write().expect("Cassette playback lock poisoned");
+            for interaction in interactions {
+                let key = key(&interaction.method, &interaction.url);
+                keys.push(key.clone());
+                playback.insert(key, interaction);
+            }
+        }
+
+        Ok(Self {
+            path,
+            recording,
+            keys,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+impl Drop for Cassette {
+    fn drop(&mut self) {
+        if !self.recording {
+            let mut playback = PLAYBACK.write().expect("Cassette playback lock poisoned");
+            for key in self.keys.iter() {
+                playback.remove(key);
+            }
+            return;
+        }
+
+        let recorded = RECORDER
+            .lock()
+            .expect("Cassette recorder lock poisoned")
+            .take()
+            .unwrap_or_default();
+        if let Some(parent) = self.path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        match serde_json::to_string_pretty(&recorded) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&self.path, json) {
+                    eprintln!("Failed to write cassette {}: {}", self.path.display(), e);
+                }
+            }
+            Err(e) => eprintln!("Failed to serialize cassette: {}", e),
+        }
+    }
+}
+
+/// Serves requests from loaded cassettes and records real responses while a
+/// cassette is recording. Unknown requests fail unless `ULTRON_RECORD` is set,
+/// so a drifted URL never reaches the live sources; only local mock servers
+/// are reached directly.
+pub struct CassetteMiddleware;
+
+fn is_loopback(req: &Request) -> bool {
+    matches!(
+        req.url().host_str(),
+        Some("127.0.0.1" | "localhost" | "[::1]")
+    )
+}
+
+#[async_trait]
+impl Middleware for CassetteMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let method = req.method().to_string();
+        let url = req.url().to_string();
+        let recorded = PLAYBACK
+            .read()
+            .expect("Cassette playback lock poisoned")
+            .get(&key(&method, &url))
+            .cloned();
+        if let Some(interaction) = recorded {
+            return interaction
+                .to_response()
+                .map_err(reqwest_middleware::Error::Middleware);
+        }
+        if !is_loopback(&req) && std::env::var_os("ULTRON_RECORD").is_none() {
+            return Err(reqwest_middleware::Error::Middleware(anyhow::anyhow!(
+                "No recording of {} in the inserted cassettes, set ULTRON_RECORD to record it",
+                key(&method, &url)
+            )));
+        }
+
+        let resp = next.run(req, extensions).await?;
+        if RECORDER
+            .lock()
+            .expect("Cassette recorder lock poisoned")
+            .is_none()
+        {
+            return Ok(resp);
+        }
+
+        let status = resp.status().as_u16();
+        let headers = resp
+            .headers()
+            .iter()
+            .filter(|(name, _)| *name != http::header::TRANSFER_ENCODING)
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.to_string(), value.to_owned()))
+            })
+            .collect();
+        let body = resp.bytes().await?;
+        let interaction = Interaction {
+            method,
+            url,
+            status,
+            headers,
+            body: BASE64.encode(&body),
+        };
+
+        let response = interaction
+            .to_response()
+            .map_err(reqwest_middleware::Error::Middleware)?;
+        if let Some(recorder) = RECORDER
+            .lock()
+            .expect("Cassette recorder lock poisoned")
+            .as_mut()
+        {
+            recorder.push(interaction);
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest_middleware::ClientBuilder;
+
+    #[tokio::test]
+    async fn test_unknown_requests_fail() {
+        if std::env::var_os("ULTRON_RECORD").is_some() {
+            return;
+        }
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(CassetteMiddleware)
+            .build();
+        let error = client
+            .get("https://www.twse.com.tw/unrecorded")
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(error, reqwest_middleware::Error::Middleware(_)));
+        assert!(error
+            .to_string()
+            .contains("GET https://www.twse.com.tw/unrecorded"));
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_playback_ends_with_cassette() {
+        if std::env::var_os("ULTRON_RECORD").is_some() {
+            return;
+        }
+        let url = "https://www.twse.com.tw/scoped";
+        let path =
+            std::env::temp_dir().join(format!("ultron-cassette-{}.json", std::process::id()));
+        let interaction = Interaction {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            status: 200,
+            headers: vec![],
+            body: BASE64.encode("ok"),
+        };
+        std::fs::write(&path, serde_json::to_string(&vec![interaction]).unwrap()).unwrap();
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(CassetteMiddleware)
+            .build();
+
+        let cassette = Cassette::insert(&path).unwrap();
+        let resp = client.get(url).send().await.unwrap();
+        assert_eq!(resp.text().await.unwrap(), "ok");
+        drop(cassette);
+        assert!(client.get(url).send().await.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_synthetic_cassettes_are_consistent() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cassettes/synthetic");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let content = std::fs::read_to_string(&path).unwrap();
+            let interactions: Vec<Interaction> = serde_json::from_str(&content).unwrap();
+            for interaction in interactions {
+                let body = BASE64.decode(&interaction.body).unwrap();
+                let length = interaction
+                    .headers
+                    .iter()
+                    .find(|(name, _)| name == "content-length")
+                    .map(|(_, value)| value.parse::<usize>().unwrap());
+                assert_eq!(length, Some(body.len()), "{}", interaction.url);
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use encoding_rs::{Encoding, BIG5, UTF_8};
+use lazy_static::lazy_static;
+use regex::bytes::Regex;
+
+use super::FetchError;
+
+lazy_static! {
+    static ref META_CHARSET: Regex =
+        Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?([a-z0-9_-]+)"#).unwrap();
+}
+
+// how far into an HTML document a <meta charset> is looked for
+const META_SNIFF_LEN: usize = 2048;
+
+/// Body decoded into UTF-8
+#[derive(Debug)]
+pub struct Decoded {
+    pub text: String,
+    /// U+FFFD characters produced by malformed input
+    pub replacements: usize,
+}
+
+/// Pick the encoding of a body: BOM first, then content that can only be UTF-8,
+/// then the declared charset (header, then HTML meta), then exchange defaults.
+pub fn detect(content_type: &str, body: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        return encoding;
+    }
+
+    // Big5 text is practically never valid UTF-8, the reverse is not true
+    if !body.is_ascii() && is_utf8_prefix(body) {
+        return UTF_8;
+    }
+
+    if let Some(encoding) = content_type_charset(content_type) {
+        return encoding;
+    }
+
+    let head = &body[..body.len().min(META_SNIFF_LEN)];
+    if let Some(encoding) = META_CHARSET
+        .captures(head)
+        .and_then(|captures| captures.get(1))
+        .and_then(|label| for_label(label.as_bytes()))
+    {
+        return encoding;
+    }
+
+    // exchanges serve their legacy exports as Big5 without declaring it
+    let content_type = content_type.to_ascii_lowercase();
+    if content_type.contains("csv") || !body.is_ascii() {
+        BIG5
+    } else {
+        UTF_8
+    }
+}
+
+/// Decode a body, rejecting malformed input unless `lossy` is set
+pub fn decode(content_type: &str, body: &[u8], lossy: bool) -> Result<Decoded, FetchError> {
+    let encoding = detect(content_type, body);
+    let (text, encoding, _) = encoding.decode(body);
+    let replacements = text.matches('\u{FFFD}').count();
+    if replacements > 0 && !lossy {
+        return Err(FetchError::Decode(format!(
+            "{} malformed sequences decoding as {}",
+            replacements,
+            encoding.name()
+        )));
+    }
+
+    Ok(Decoded {
+        text: text.into_owned(),
+        replacements,
+    })
+}
+
+/// Valid UTF-8, allowing a character cut at the end of a streamed head
+fn is_utf8_prefix(body: &[u8]) -> bool {
+    match std::str::from_utf8(body) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+fn content_type_charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, value)| for_label(value.trim().trim_matches('"').as_bytes()))
+}
+
+fn for_label(label: &[u8]) -> Option<&'static Encoding> {
+    // Microsoft code page names used by TWSE/TPEx are not WHATWG labels
+    if label.eq_ignore_ascii_case(b"ms950") || label.eq_ignore_ascii_case(b"cp950") {
+        return Some(BIG5);
+    }
+    Encoding::for_label(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_utf8_csv() {
+        // TWSE rwd endpoints serve UTF-8 CSV with a csv content-type
+        let body = "\"證券代號\",\"證券名稱\"".as_bytes();
+        assert_eq!(detect("text/csv", body), UTF_8);
+        assert_eq!(detect("text/csv", &body[..body.len() - 2]), UTF_8);
+    }
+
+    #[test]
+    fn test_detect_big5_csv() {
+        let (body, _, _) = BIG5.encode("\"證券代號\",\"證券名稱\"");
+        assert_eq!(detect("application/csv", &body), BIG5);
+        assert_eq!(detect("text/csv; charset=MS950", &body), BIG5);
+    }
+
+    #[test]
+    fn test_detect_bom_and_meta() {
+        assert_eq!(detect("text/csv", b"\xEF\xBB\xBFa,b"), UTF_8);
+
+        let (body, _, _) = BIG5.encode(r#"<html><head><meta charset="big5"></head>合計</html>"#);
+        assert_eq!(detect("text/html", &body), BIG5);
+    }
+
+    #[test]
+    fn test_decode_lossy() {
+        let mut body = BIG5.encode("台積電").0.into_owned();
+        body.push(0xFF);
+
+        assert!(decode("text/csv; charset=big5", &body, false).is_err());
+
+        let decoded = decode("text/csv; charset=big5", &body, true).unwrap();
+        assert_eq!(decoded.replacements, 1);
+        assert!(decoded.text.starts_with("台積電"));
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::FetchError;
+use crate::config::setting::CircuitBreakerSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // a single probe request is in flight, given up on at `deadline`
+    HalfOpen { deadline: Instant },
+}
+
+/// Per-host circuit breaker: after `failure_threshold` consecutive failures the
+/// host is skipped for `cooldown_secs`, then one probe decides whether it closes.
+/// A probe cancelled before its outcome is recorded lets another one through
+/// after `probe_timeout_secs`.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    probe_timeout: Duration,
+    states: Mutex<HashMap<String, State>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(settings: &CircuitBreakerSettings) -> Self {
+        Self {
+            failure_threshold: settings.failure_threshold.max(1),
+            cooldown: Duration::from_secs(settings.cooldown_secs),
+            probe_timeout: Duration::from_secs(settings.probe_timeout_secs),
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fail fast when the circuit of the host is open
+    pub fn check(&self, host: &str) -> Result<(), FetchError> {
+        let mut states = self.states.lock().expect("Circuit breaker lock poisoned");
+        let state = states
+            .entry(host.to_owned())
+            .or_insert(State::Closed { failures: 0 });
+
+        let now = Instant::now();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now >= until => {
+                println!("Circuit breaker for {} half-open, sending probe", host);
+                *state = State::HalfOpen {
+                    deadline: now + self.probe_timeout,
+                };
+                Ok(())
+            }
+            State::HalfOpen { deadline } if now >= deadline => {
+                println!("Circuit breaker for {} probe lost, sending another", host);
+                *state = State::HalfOpen {
+                    deadline: now + self.probe_timeout,
+                };
+                Ok(())
+            }
+            State::Open { .. } | State::HalfOpen { .. } => {
+                Err(FetchError::CircuitOpen(host.to_owned()))
+            }
+        }
+    }
+
+    pub fn record_success(&self, host: &str) {
+        let mut states = self.states.lock().expect("Circuit breaker lock poisoned");
+        let previous = states.insert(host.to_owned(), State::Closed { failures: 0 });
+        if matches!(previous, Some(State::HalfOpen { .. } | State::Open { .. })) {
+            println!("Circuit breaker for {} closed", host);
+        }
+    }
+
+    pub fn record_failure(&self, host: &str) {
+        let mut states = self.states.lock().expect("Circuit breaker lock poisoned");
+        let state = states
+            .entry(host.to_owned())
+            .or_insert(State::Closed { failures: 0 });
+
+        match *state {
+            State::Closed { failures } if failures + 1 < self.failure_threshold => {
+                *state = State::Closed {
+                    failures: failures + 1,
+                };
+            }
+            State::Closed { .. } => {
+                println!(
+                    "Circuit breaker for {} opened after {} consecutive failures, cooling down {}s",
+                    host,
+                    self.failure_threshold,
+                    self.cooldown.as_secs()
+                );
+                *state = State::Open {
+                    until: Instant::now() + self.cooldown,
+                };
+            }
+            State::HalfOpen { .. } => {
+                println!("Circuit breaker for {} probe failed, re-opened", host);
+                *state = State::Open {
+                    until: Instant::now() + self.cooldown,
+                };
+            }
+            State::Open { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(cooldown_secs: u64) -> CircuitBreaker {
+        CircuitBreaker::new(&CircuitBreakerSettings {
+            failure_threshold: 3,
+            cooldown_secs,
+            probe_timeout_secs: 60,
+        })
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = breaker(60);
+        for _ in 0..2 {
+            breaker.record_failure("fubon-ebrokerdj.fbs.com.tw");
+            assert!(breaker.check("fubon-ebrokerdj.fbs.com.tw").is_ok());
+        }
+        breaker.record_failure("fubon-ebrokerdj.fbs.com.tw");
+        assert!(breaker.check("fubon-ebrokerdj.fbs.com.tw").is_err());
+        assert!(breaker.check("www.twse.com.tw").is_ok());
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let breaker = breaker(60);
+        breaker.record_failure("www.twse.com.tw");
+        breaker.record_failure("www.twse.com.tw");
+        breaker.record_success("www.twse.com.tw");
+        breaker.record_failure("www.twse.com.tw");
+        assert!(breaker.check("www.twse.com.tw").is_ok());
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breaker = breaker(0);
+        for _ in 0..3 {
+            breaker.record_failure("www.tpex.org.tw");
+        }
+
+        // cooldown elapsed, only one probe goes through
+        assert!(breaker.check("www.tpex.org.tw").is_ok());
+        assert!(breaker.check("www.tpex.org.tw").is_err());
+
+        breaker.record_success("www.tpex.org.tw");
+        assert!(breaker.check("www.tpex.org.tw").is_ok());
+    }
+
+    #[test]
+    fn test_lost_probe_lets_another_through() {
+        let breaker = CircuitBreaker::new(&CircuitBreakerSettings {
+            failure_threshold: 1,
+            cooldown_secs: 0,
+            probe_timeout_secs: 0,
+        });
+        breaker.record_failure("www.tpex.org.tw");
+
+        // the first probe is dropped without recording an outcome
+        assert!(breaker.check("www.tpex.org.tw").is_ok());
+        assert!(breaker.check("www.tpex.org.tw").is_ok());
+        breaker.record_success("www.tpex.org.tw");
+        assert!(breaker.check("www.tpex.org.tw").is_ok());
+    }
+}
@@ -0,0 +1,116 @@
+use flate2::read::GzDecoder;
+use std::io::{Cursor, Read};
+
+use super::FetchError;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+// local file header, or the end of central directory record of an empty archive
+const ZIP_MAGIC: &[&[u8]] = &[b"PK\x03\x04", b"PK\x05\x06"];
+
+/// Body after transparent decompression, told apart by magic bytes
+#[derive(Debug)]
+pub enum Expanded {
+    Plain(Vec<u8>),
+    Gzip(Vec<u8>),
+    /// every inner file as `(inner filename, bytes)`
+    Zip(Vec<(String, Vec<u8>)>),
+}
+
+/// Whether a body, or its first bytes, is a gzip stream or zip archive
+pub fn is_compressed(head: &[u8]) -> bool {
+    head.starts_with(GZIP_MAGIC) || ZIP_MAGIC.iter().any(|magic| head.starts_with(magic))
+}
+
+/// Decompress a gzip stream or zip archive, anything else is passed through
+pub fn expand(bytes: Vec<u8>) -> Result<Expanded, FetchError> {
+    if bytes.starts_with(GZIP_MAGIC) {
+        Ok(Expanded::Gzip(gunzip(&bytes)?))
+    } else if ZIP_MAGIC.iter().any(|magic| bytes.starts_with(magic)) {
+        Ok(Expanded::Zip(unzip(&bytes)?))
+    } else {
+        Ok(Expanded::Plain(bytes))
+    }
+}
+
+/// Inflate a gzip stream
+pub fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, FetchError> {
+    let mut inflated = Vec::new();
+    GzDecoder::new(bytes)
+        .read_to_end(&mut inflated)
+        .map_err(|e| FetchError::Decode(format!("invalid gzip stream: {}", e)))?;
+    Ok(inflated)
+}
+
+/// Extract every file of a zip archive as `(inner filename, bytes)`
+pub fn unzip(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, FetchError> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| FetchError::Decode(format!("invalid zip archive: {}", e)))?;
+
+    let mut files = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        let mut file = archive
+            .by_index(index)
+            .map_err(|e| FetchError::Decode(format!("invalid zip entry: {}", e)))?;
+        if file.is_dir() {
+            continue;
+        }
+
+        let mut content = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut content)?;
+        files.push((file.name().to_owned(), content));
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    #[test]
+    fn test_gunzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all("證券代號,證券名稱".as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(gunzip(&compressed).unwrap(), "證券代號,證券名稱".as_bytes());
+        assert!(gunzip(b"not gzip").is_err());
+    }
+
+    #[test]
+    fn test_unzip() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        writer.start_file("twse.csv", options).unwrap();
+        writer.write_all(b"2330,TSMC").unwrap();
+        writer.start_file("tpex.csv", options).unwrap();
+        writer.write_all(b"6488,GlobalWafers").unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let files = unzip(&bytes).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0], ("twse.csv".to_string(), b"2330,TSMC".to_vec()));
+        assert_eq!(files[1].0, "tpex.csv");
+    }
+
+    #[test]
+    fn test_expand_by_magic() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"2330,TSMC").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert!(
+            matches!(expand(compressed).unwrap(), Expanded::Gzip(bytes) if bytes == b"2330,TSMC")
+        );
+        assert!(matches!(
+            expand(b"PK".to_vec()).unwrap(),
+            Expanded::Plain(_)
+        ));
+        assert!(matches!(
+            expand(b"PK\x03\x04broken".to_vec()),
+            Err(FetchError::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,76 @@
+use reqwest::StatusCode;
+use std::fmt;
+
+/// Failure classes of a fetch, so callers can tell a holiday 404 from a
+/// throttled or broken upstream and decide to skip or abort.
+#[derive(Debug)]
+pub enum FetchError {
+    /// Nothing published at the source, e.g. a market holiday
+    NotFound(String),
+    /// Upstream throttled, blocked or in maintenance
+    Throttled(String),
+    Timeout(String),
+    Http(StatusCode),
+    Decode(String),
+    Proxy(String),
+    Io(std::io::Error),
+    /// Circuit breaker of the host is open, the request was never sent
+    CircuitOpen(String),
+    /// Any other failure while sending the request
+    Request(String),
+    Unsupported(String),
+}
+
+impl FetchError {
+    /// Classify a failed request, `via_proxy` tells connection errors apart
+    pub fn from_request(e: reqwest_middleware::Error, via_proxy: bool) -> Self {
+        match e {
+            reqwest_middleware::Error::Reqwest(e) => Self::from_reqwest(e, via_proxy),
+            reqwest_middleware::Error::Middleware(e) => FetchError::Request(e.to_string()),
+        }
+    }
+
+    pub fn from_reqwest(e: reqwest::Error, via_proxy: bool) -> Self {
+        if e.is_timeout() {
+            FetchError::Timeout(e.to_string())
+        } else if e.is_connect() && via_proxy {
+            FetchError::Proxy(e.to_string())
+        } else if e.is_decode() || e.is_body() {
+            FetchError::Decode(e.to_string())
+        } else {
+            FetchError::Request(e.to_string())
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::NotFound(source) => write!(f, "Not found: {}", source),
+            FetchError::Throttled(reason) => write!(f, "Throttled: {}", reason),
+            FetchError::Timeout(e) => write!(f, "Timeout: {}", e),
+            FetchError::Http(status) => write!(f, "Unexpected HTTP status: {}", status),
+            FetchError::Decode(e) => write!(f, "Failed to decode: {}", e),
+            FetchError::Proxy(e) => write!(f, "Proxy error: {}", e),
+            FetchError::Io(e) => write!(f, "IO error: {}", e),
+            FetchError::CircuitOpen(host) => write!(f, "Circuit open for {}", host),
+            FetchError::Request(e) => write!(f, "Request failed: {}", e),
+            FetchError::Unsupported(source) => write!(f, "Unsupported source: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FetchError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for FetchError {
+    fn from(e: std::io::Error) -> Self {
+        FetchError::Io(e)
+    }
+}
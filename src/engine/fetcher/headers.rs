@@ -0,0 +1,125 @@
+use async_trait::async_trait;
+use http::Extensions;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT_LANGUAGE, REFERER, USER_AGENT};
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::setting::HeaderSettings;
+
+/// Applies the header profile of the request host, rotating its User-Agents.
+/// Headers already set on the request are left alone.
+pub struct HeaderProfileMiddleware {
+    settings: HeaderSettings,
+    cursor: AtomicUsize,
+}
+
+impl HeaderProfileMiddleware {
+    pub fn new(settings: HeaderSettings) -> Self {
+        Self {
+            settings,
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    /// Headers of the next request to the host
+    pub fn headers_for(&self, host: &str) -> HeaderMap {
+        let profile = self.settings.for_host(host);
+        let mut headers = HeaderMap::new();
+
+        if !profile.user_agents.is_empty() {
+            let index = self.cursor.fetch_add(1, Ordering::Relaxed) % profile.user_agents.len();
+            insert(&mut headers, USER_AGENT, &profile.user_agents[index]);
+        }
+        if let Some(referer) = &profile.referer {
+            insert(&mut headers, REFERER, referer);
+        }
+        if let Some(accept_language) = &profile.accept_language {
+            insert(&mut headers, ACCEPT_LANGUAGE, accept_language);
+        }
+        for (name, value) in profile.extra.iter() {
+            match HeaderName::from_bytes(name.as_bytes()) {
+                Ok(name) => insert(&mut headers, name, value),
+                Err(e) => eprintln!("Skipped malformed header name {}: {}", name, e),
+            }
+        }
+
+        headers
+    }
+}
+
+fn insert(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    match HeaderValue::from_str(value) {
+        Ok(value) => {
+            headers.insert(name, value);
+        }
+        Err(e) => eprintln!("Skipped malformed {} header: {}", name, e),
+    }
+}
+
+#[async_trait]
+impl Middleware for HeaderProfileMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let host = req.url().host_str().unwrap_or_default().to_owned();
+        for (name, value) in self.headers_for(&host) {
+            if let Some(name) = name {
+                req.headers_mut().entry(name).or_insert(value);
+            }
+        }
+
+        next.run(req, extensions).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::setting::HeaderProfile;
+    use std::collections::HashMap;
+
+    fn middleware() -> HeaderProfileMiddleware {
+        HeaderProfileMiddleware::new(HeaderSettings {
+            default: HeaderProfile {
+                user_agents: vec!["agent-a".to_string(), "agent-b".to_string()],
+                accept_language: Some("zh-TW".to_string()),
+                ..Default::default()
+            },
+            hosts: HashMap::from([(
+                "www.twse.com.tw".to_string(),
+                HeaderProfile {
+                    referer: Some("https://www.twse.com.tw/zh/".to_string()),
+                    extra: HashMap::from([(
+                        "x-requested-with".to_string(),
+                        "XMLHttpRequest".to_string(),
+                    )]),
+                    ..Default::default()
+                },
+            )]),
+        })
+    }
+
+    #[test]
+    fn test_user_agent_rotation() {
+        let middleware = middleware();
+        let agents: Vec<HeaderValue> = (0..3)
+            .map(|_| middleware.headers_for("www.tpex.org.tw")[USER_AGENT].clone())
+            .collect();
+        assert_eq!(agents, ["agent-a", "agent-b", "agent-a"]);
+    }
+
+    #[test]
+    fn test_host_profile_over_default() {
+        let headers = middleware().headers_for("www.twse.com.tw");
+        assert_eq!(headers[REFERER], "https://www.twse.com.tw/zh/");
+        assert_eq!(headers[ACCEPT_LANGUAGE], "zh-TW");
+        assert_eq!(headers["x-requested-with"], "XMLHttpRequest");
+
+        let headers = middleware().headers_for("fubon-ebrokerdj.fbs.com.tw");
+        assert!(headers.get(REFERER).is_none());
+    }
+}
@@ -0,0 +1,117 @@
+use chrono::{DateTime, Local};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// What the fetcher knows about how a payload was obtained, kept for lineage,
+/// debugging and metrics. Local sources only get the size and hash of the body.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FetchMeta {
+    /// HTTP status of the response, `None` for local sources
+    pub status: Option<u16>,
+    pub headers: Vec<(String, String)>,
+    /// RFC 3339 timestamps around the fetch
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    /// Time until the body was received. Streamed payloads get it, along with
+    /// the size and hash, once their body has been read through.
+    pub latency_ms: Option<u64>,
+    /// Raw body as received, before decompression and decoding
+    pub size: usize,
+    pub sha256: String,
+    /// Attempts beyond the first one made by the retry middleware
+    pub retries: u32,
+    /// Name of the proxy the request went through
+    pub proxy: Option<String>,
+    /// Served from the response cache, possibly after revalidation
+    pub cached: bool,
+    /// U+FFFD characters left by decoding malformed sequences leniently
+    pub replacements: usize,
+}
+
+impl FetchMeta {
+    /// Start recording a request sent now
+    pub fn start(now: DateTime<Local>) -> Self {
+        Self {
+            started_at: Some(now.to_rfc3339()),
+            ..Default::default()
+        }
+    }
+
+    /// Metadata of a body read from a local source
+    pub fn local(body: &[u8]) -> Self {
+        let mut meta = Self::default();
+        meta.record_body(body);
+        meta
+    }
+
+    /// Record the response status and headers, received at `now`
+    pub fn record_response(&mut self, status: u16, headers: &HeaderMap, now: DateTime<Local>) {
+        self.status = Some(status);
+        self.headers = headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        self.latency_ms = self.elapsed_ms(now);
+    }
+
+    /// Record the complete raw body, received at `now`
+    pub fn finish(&mut self, body: &[u8], now: DateTime<Local>) {
+        self.record_body(body);
+        self.finished_at = Some(now.to_rfc3339());
+        self.latency_ms = self.elapsed_ms(now);
+    }
+
+    /// Record a raw body of `size` bytes hashed while it streamed through,
+    /// complete at `now`. Local sources get no timestamps.
+    pub fn finish_stream(&mut self, size: usize, sha256: Sha256, now: DateTime<Local>) {
+        self.size = size;
+        self.sha256 = format!("{:x}", sha256.finalize());
+        if self.started_at.is_some() {
+            self.finished_at = Some(now.to_rfc3339());
+            self.latency_ms = self.elapsed_ms(now);
+        }
+    }
+
+    fn record_body(&mut self, body: &[u8]) {
+        self.size = body.len();
+        self.sha256 = format!("{:x}", Sha256::digest(body));
+    }
+
+    fn elapsed_ms(&self, now: DateTime<Local>) -> Option<u64> {
+        let started_at = DateTime::parse_from_rfc3339(self.started_at.as_deref()?).ok()?;
+        u64::try_from((now - started_at.with_timezone(&Local)).num_milliseconds()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_record_fetch() {
+        let started_at = Local::now();
+        let mut meta = FetchMeta::start(started_at);
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "text/csv".parse().unwrap());
+        meta.record_response(200, &headers, started_at + Duration::milliseconds(120));
+        assert_eq!(meta.latency_ms, Some(120));
+        assert_eq!(
+            meta.headers,
+            vec![("content-type".to_string(), "text/csv".to_string())]
+        );
+
+        meta.finish(b"2330", started_at + Duration::milliseconds(450));
+        assert_eq!(meta.status, Some(200));
+        assert_eq!(meta.latency_ms, Some(450));
+        assert_eq!(meta.size, 4);
+        assert_eq!(meta.sha256, FetchMeta::local(b"2330").sha256);
+    }
+}
@@ -0,0 +1,882 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::Local;
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use reqwest::header::{HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use tokio_util::io::ReaderStream;
+
+use crate::config::setting::{ProxySettings, RetryPolicy, SETTINGS};
+
+pub mod archive;
+pub mod block;
+mod cache;
+pub mod cassette;
+mod charset;
+mod circuit_breaker;
+mod compression;
+mod error;
+mod headers;
+mod meta;
+mod proxy;
+mod rate_limit;
+mod request;
+mod retry;
+pub mod scheme;
+mod session;
+mod stream;
+
+use archive::{Archive, ArchiveEntry, ArchiveWriter};
+use block::{BlockDetectionMiddleware, BLOCKED_HEADER};
+use cache::{CacheWriter, CachedResponse, ResponseCache};
+use circuit_breaker::CircuitBreaker;
+pub use error::FetchError;
+use headers::HeaderProfileMiddleware;
+pub use meta::FetchMeta;
+use proxy::{ProxyEntry, ProxyPool};
+use rate_limit::RateLimiter;
+pub use request::{FetchRequest, Method, RequestBody};
+use retry::{AttemptCounterMiddleware, Attempts, StatusRetryStrategy};
+use session::{Session, SessionPool};
+pub use stream::PayloadStream;
+use stream::{ByteStream, SNIFF_LEN};
+
+lazy_static! {
+    static ref RATE_LIMITER: RateLimiter = RateLimiter::new(SETTINGS.rate_limit.clone());
+    static ref CIRCUIT_BREAKER: CircuitBreaker = CircuitBreaker::new(&SETTINGS.circuit_breaker);
+    // tests must never leave their payloads in the configured archive
+    static ref ARCHIVE: Option<Archive> = (SETTINGS.archive.enabled
+        && !cfg!(any(test, feature = "testing")))
+    .then(|| Archive::new(&SETTINGS.archive.path));
+    // tests must always reach their mocks and cassettes
+    static ref CACHE: Option<ResponseCache> = (SETTINGS.cache.enabled
+        && !cfg!(any(test, feature = "testing")))
+    .then(|| ResponseCache::new(&SETTINGS.cache));
+}
+
+lazy_static! {
+    static ref PROXY_POOL: ProxyPool = if cfg!(feature = "testing") {
+        ProxyPool::new(&ProxySettings::default())
+    } else {
+        ProxyPool::new(&SETTINGS.proxy)
+    };
+    // one client per host and proxy, following the retry policy of the host
+    static ref CLIENTS: Mutex<HashMap<String, ClientWithMiddleware>> = Mutex::new(HashMap::new());
+    static ref SESSIONS: SessionPool = SessionPool::new(&SETTINGS.sessions);
+}
+
+fn build_client(
+    proxy: Option<reqwest::Proxy>,
+    cookies: Option<Arc<reqwest::cookie::Jar>>,
+    policy: &RetryPolicy,
+) -> ClientWithMiddleware {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(policy.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(policy.read_timeout_secs))
+        .timeout(Duration::from_secs(policy.timeout_secs));
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(cookies) = cookies {
+        builder = builder.cookie_provider(cookies);
+    }
+    let client = builder.build().expect("Failed to create Client");
+
+    // Retry with exponentially increasing intervals between attempts
+    let min_backoff = Duration::from_millis(policy.min_backoff_ms);
+    let retry_policy = ExponentialBackoff::builder()
+        .retry_bounds(
+            min_backoff,
+            Duration::from_millis(policy.max_backoff_ms).max(min_backoff),
+        )
+        .build_with_max_retries(policy.max_retries);
+    let builder = ClientBuilder::new(client)
+        .with(RetryTransientMiddleware::new_with_policy_and_strategy(
+            retry_policy,
+            StatusRetryStrategy::new(&policy.retryable_statuses),
+        ))
+        .with(AttemptCounterMiddleware)
+        // Block pages come back as 429 so the retry above backs off
+        .with(BlockDetectionMiddleware)
+        .with(HeaderProfileMiddleware::new(SETTINGS.headers.clone()));
+    if cfg!(feature = "testing") {
+        // Serve recorded cassettes before anything reaches the network
+        builder.with(cassette::CassetteMiddleware).build()
+    } else {
+        builder.build()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Payload {
+    pub content: String,
+    pub source: String,
+    pub content_type: String,
+    pub date: Option<String>,
+    /// Request that produced the content, `None` for local sources
+    pub request: Option<FetchRequest>,
+    pub meta: FetchMeta,
+}
+
+#[async_trait]
+pub trait Fetch: Send + Sync {
+    type Error;
+    async fn fetch(&self) -> Result<Payload, Self::Error>;
+
+    /// Sources holding several documents (directories, archives) yield one
+    /// payload per document
+    async fn fetch_all(&self) -> Result<Vec<Payload>, Self::Error> {
+        Ok(vec![self.fetch().await?])
+    }
+
+    /// Body decoded while it is read, buffered unless the source can stream
+    async fn fetch_stream(&self) -> Result<PayloadStream, Self::Error> {
+        Ok(PayloadStream::from(self.fetch().await?))
+    }
+}
+
+/// Exctract content from data source, the fetcher is picked by URI scheme
+// entry point for one-off fetches, targets in the binary all archive theirs
+#[allow(dead_code)]
+pub async fn fetch_content(source: impl Into<FetchRequest>) -> Result<Payload, FetchError> {
+    let request = source.into();
+    let fetcher = resolve_request(&request)?;
+    fetcher.fetch().await
+}
+
+/// Extract every payload of a multi-document source
+pub async fn fetch_all_content(
+    source: impl Into<FetchRequest>,
+) -> Result<Vec<Payload>, FetchError> {
+    let request = source.into();
+    let fetcher = resolve_request(&request)?;
+    fetcher.fetch_all().await
+}
+
+/// Extract content as a stream, keeping memory flat on large files
+pub async fn fetch_stream(source: impl Into<FetchRequest>) -> Result<PayloadStream, FetchError> {
+    let request = source.into();
+    let fetcher = resolve_request(&request)?;
+    fetcher.fetch_stream().await
+}
+
+/// Bare GETs go through the scheme registry, anything richer needs HTTP
+fn resolve_request(
+    request: &FetchRequest,
+) -> Result<Box<dyn Fetch<Error = FetchError> + '_>, FetchError> {
+    if request.is_plain_get() {
+        return scheme::resolve(&request.url);
+    }
+
+    match request.url.split_once("://") {
+        Some((scheme, _))
+            if scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https") =>
+        {
+            Ok(Box::new(UrlFetcher(request.clone())))
+        }
+        _ => Err(FetchError::Unsupported(request.url.clone())),
+    }
+}
+
+fn decode_payload(source: &str, content_type: &str, bytes: &[u8]) -> Result<Payload, FetchError> {
+    let decoded = charset::decode(content_type, bytes, SETTINGS.decoding.lossy)?;
+    Ok(Payload {
+        content: decoded.text,
+        source: source.to_owned(),
+        content_type: content_type.to_owned(),
+        date: None,
+        request: None,
+        meta: FetchMeta {
+            replacements: decoded.replacements,
+            ..Default::default()
+        },
+    })
+}
+
+/// Decode a body into payloads, inflating gzip streams and fanning zip archives
+/// out into one payload per inner file, named after it. Every payload shares
+/// the metadata of the body it came from, apart from its own replacements.
+fn decode_documents(
+    source: &str,
+    name: &str,
+    content_type: &str,
+    bytes: Vec<u8>,
+    meta: &FetchMeta,
+) -> Result<Vec<Payload>, FetchError> {
+    let mut payloads = match compression::expand(bytes)? {
+        compression::Expanded::Plain(bytes) => {
+            vec![decode_payload(source, content_type, &bytes)?]
+        }
+        compression::Expanded::Gzip(bytes) => {
+            // the declared type describes the compressed stream, not the content
+            let inner_name = name.strip_suffix(".gz").unwrap_or(name);
+            let content_type = match content_type {
+                "" | "application/gzip" | "application/x-gzip" | "application/octet-stream" => {
+                    content_type_for(inner_name)
+                }
+                declared => declared,
+            };
+            vec![decode_payload(source, content_type, &bytes)?]
+        }
+        compression::Expanded::Zip(files) => files
+            .iter()
+            .map(|(inner, bytes)| decode_payload(inner, content_type_for(inner), bytes))
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    for payload in payloads.iter_mut() {
+        payload.meta = FetchMeta {
+            replacements: payload.meta.replacements,
+            ..meta.clone()
+        };
+    }
+    Ok(payloads)
+}
+
+/// Return the only payload of a multi-payload source
+fn single(source: &str, mut payloads: Vec<Payload>) -> Result<Payload, FetchError> {
+    if payloads.len() == 1 {
+        Ok(payloads.remove(0))
+    } else {
+        Err(FetchError::Unsupported(format!(
+            "{} holds {} payloads",
+            source,
+            payloads.len()
+        )))
+    }
+}
+
+/// Guess the content type of a local file from its name
+fn content_type_for(name: &str) -> &'static str {
+    match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => "text/csv",
+        Some("html" | "htm" | "djhtm") => "text/html",
+        Some("json") => "application/json",
+        _ => "text/plain",
+    }
+}
+
+/// Extract content and keep a copy of the raw body in the local archive, grouped
+/// by crawling target and trading date. Archive failures never fail the fetch.
+pub async fn fetch_and_archive(
+    source: impl Into<FetchRequest>,
+    target: &str,
+    date: &str,
+) -> Result<Payload, FetchError> {
+    let mut stream = fetch_stream_and_archive(source, target, date).await?;
+    let mut content = String::new();
+    stream.read_to_string(&mut content).await?;
+
+    Ok(Payload {
+        content,
+        meta: stream.meta(),
+        source: stream.source,
+        content_type: stream.content_type,
+        date: stream.date,
+        request: stream.request,
+    })
+}
+
+/// Streaming counterpart of `fetch_and_archive`, the raw body is archived as it
+/// is read and only kept once it has been read through.
+pub async fn fetch_stream_and_archive(
+    source: impl Into<FetchRequest>,
+    target: &str,
+    date: &str,
+) -> Result<PayloadStream, FetchError> {
+    let payload = fetch_stream(source).await?;
+    let Some(archive) = ARCHIVE.as_ref() else {
+        return Ok(payload);
+    };
+
+    match archive.writer(target, date, &payload, Local::now()) {
+        Ok(writer) => {
+            let sink = copy_to(
+                &payload.source,
+                writer,
+                ArchiveWriter::write,
+                ArchiveWriter::finish,
+            );
+            Ok(payload.tee(sink))
+        }
+        Err(e) => {
+            eprintln!("Failed to archive payload {}: {}", payload.source, e);
+            Ok(payload)
+        }
+    }
+}
+
+/// Sink of `stream::tee` copying chunks into a writer, finished once the body
+/// is complete. A failing writer is dropped without failing the stream.
+fn copy_to<W: Send + 'static, R: 'static>(
+    source: &str,
+    writer: W,
+    write: fn(&mut W, &[u8]) -> anyhow::Result<()>,
+    finish: fn(W) -> anyhow::Result<R>,
+) -> impl FnMut(Option<&[u8]>) + Send + 'static {
+    let source = source.to_owned();
+    let mut writer = Some(writer);
+    move |chunk| {
+        let result = match (chunk, writer.as_mut()) {
+            (Some(chunk), Some(w)) => write(w, chunk),
+            (None, Some(_)) => writer.take().map_or(Ok(()), |w| finish(w).map(|_| ())),
+            (_, None) => Ok(()),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to store streamed body {}: {}", source, e);
+            writer = None;
+        }
+    }
+}
+
+/// Client picked for one request
+struct CrawlingClient {
+    client: ClientWithMiddleware,
+    proxy: Option<&'static ProxyEntry>,
+    session: Option<Arc<Session>>,
+}
+
+/// Pick the client for a host along with the proxy it goes through, if any.
+/// Every client sends the header profile configured for the request host, and
+/// hosts declaring a warm-up URL get a session client holding their cookies.
+async fn crawling_client(host: &str) -> Result<CrawlingClient, FetchError> {
+    let proxy = PROXY_POOL.select(host);
+    let proxy_name = proxy.map(|entry| entry.name.as_str());
+    let build = |cookies| {
+        build_client(
+            proxy.map(|entry| entry.proxy.clone()),
+            cookies,
+            SETTINGS.retry.for_host(host),
+        )
+    };
+    let Some(profile) = SESSIONS.profile(host) else {
+        let client = CLIENTS
+            .lock()
+            .expect("Client pool lock poisoned")
+            .entry(format!("{}|{}", host, proxy_name.unwrap_or("direct")))
+            .or_insert_with(|| build(None))
+            .clone();
+        return Ok(CrawlingClient {
+            client,
+            proxy,
+            session: None,
+        });
+    };
+
+    let session = SESSIONS.session(host, proxy_name, |jar| build(Some(jar)));
+    session.warm_up(profile, proxy.is_some()).await?;
+    Ok(CrawlingClient {
+        client: session.client.clone(),
+        proxy,
+        session: Some(session),
+    })
+}
+
+struct UrlFetcher(pub(crate) FetchRequest);
+struct FileFetcher<'a>(pub(crate) &'a str);
+
+#[async_trait]
+impl Fetch for UrlFetcher {
+    type Error = FetchError;
+
+    async fn fetch(&self) -> Result<Payload, Self::Error> {
+        single(&self.0.url, self.fetch_all().await?)
+    }
+
+    async fn fetch_all(&self) -> Result<Vec<Payload>, Self::Error> {
+        let (content_type, body, meta) = self.fetch_body().await?;
+        let name = reqwest::Url::parse(&self.0.url)
+            .map(|url| url.path().to_owned())
+            .unwrap_or_default();
+        let mut payloads = decode_documents(&self.0.full_url(), &name, &content_type, body, &meta)?;
+        for payload in payloads.iter_mut() {
+            payload.request = Some(self.0.clone());
+        }
+        Ok(payloads)
+    }
+
+    async fn fetch_stream(&self) -> Result<PayloadStream, Self::Error> {
+        UrlFetcher::fetch_stream(self).await
+    }
+}
+
+/// Response to a request, or the cached entry standing in for it
+enum Fetched {
+    Cached(CachedResponse, FetchMeta),
+    /// Successful response, its body not read yet
+    Response(reqwest::Response, FetchMeta),
+}
+
+impl UrlFetcher {
+    /// Raw body of the request along with its declared content type
+    async fn fetch_body(&self) -> Result<(String, Vec<u8>, FetchMeta), FetchError> {
+        let target = &self.0.full_url();
+        let cache = self.cache();
+        let (resp, mut meta) = match self.send(cache).await? {
+            Fetched::Cached(entry, mut meta) => {
+                meta.finish(&entry.body, Local::now());
+                return Ok((self.0.content_type(entry.content_type), entry.body, meta));
+            }
+            Fetched::Response(resp, meta) => (resp, meta),
+        };
+        let via_proxy = meta.proxy.is_some();
+
+        let content_type = self.get_content_type(resp.headers());
+        let headers = resp.headers().clone();
+        let raw_body = resp
+            .bytes()
+            .await
+            .map_err(|e| FetchError::from_reqwest(e, via_proxy))?;
+        meta.finish(&raw_body, Local::now());
+        if let Some(cache) = cache {
+            if let Err(e) = cache
+                .store(target, &content_type, &headers, &raw_body, Local::now())
+                .await
+            {
+                eprintln!("Failed to cache response {}: {}", target, e);
+            }
+        }
+        Ok((self.0.content_type(content_type), raw_body.to_vec(), meta))
+    }
+
+    /// Decoded body streamed as it arrives. Compressed bodies are inflated as a
+    /// whole, then served from memory.
+    async fn fetch_stream(&self) -> Result<PayloadStream, FetchError> {
+        let target = &self.0.full_url();
+        let cache = self.cache();
+        let (content_type, body, mut meta): (String, ByteStream, FetchMeta) =
+            match self.send(cache).await? {
+                Fetched::Cached(entry, meta) => {
+                    let body = Bytes::from(entry.body);
+                    (
+                        entry.content_type,
+                        Box::pin(futures::stream::once(async move { Ok(body) })),
+                        meta,
+                    )
+                }
+                Fetched::Response(resp, meta) => {
+                    let via_proxy = meta.proxy.is_some();
+                    let content_type = self.get_content_type(resp.headers());
+                    let writer = cache.and_then(|cache| {
+                        cache
+                            .writer(target, &content_type, resp.headers(), Local::now())
+                            .map_err(|e| eprintln!("Failed to cache response {}: {}", target, e))
+                            .ok()
+                    });
+                    let body: ByteStream = Box::pin(resp.bytes_stream().map(move |chunk| {
+                        chunk.map_err(|e| io::Error::other(FetchError::from_reqwest(e, via_proxy)))
+                    }));
+                    let body = match writer {
+                        Some(writer) => stream::tee(
+                            body,
+                            copy_to(target, writer, CacheWriter::write, CacheWriter::finish),
+                        ),
+                        None => body,
+                    };
+                    (content_type, body, meta)
+                }
+            };
+
+        let content_type = self.0.content_type(content_type);
+        let mut body = body;
+        let head = stream::read_head(&mut body, SNIFF_LEN).await?;
+        if compression::is_compressed(&head) {
+            let mut raw = head.to_vec();
+            while let Some(chunk) = body.next().await {
+                raw.extend_from_slice(&chunk?);
+            }
+            meta.finish(&raw, Local::now());
+            let name = reqwest::Url::parse(&self.0.url)
+                .map(|url| url.path().to_owned())
+                .unwrap_or_default();
+            let raw = Bytes::from(raw);
+            let mut payload = single(
+                target,
+                decode_documents(target, &name, &content_type, raw.to_vec(), &meta)?,
+            )?;
+            payload.request = Some(self.0.clone());
+            return Ok(PayloadStream::buffered(payload, raw));
+        }
+
+        let mut payload = PayloadStream::decode(
+            target,
+            &content_type,
+            head,
+            body,
+            meta,
+            SETTINGS.decoding.lossy,
+        );
+        payload.request = Some(self.0.clone());
+        Ok(payload)
+    }
+
+    /// Response cache, for bodiless GETs only as they are identified by URL alone
+    fn cache(&self) -> Option<&'static ResponseCache> {
+        CACHE
+            .as_ref()
+            .filter(|_| self.0.method == Method::Get && self.0.body == RequestBody::Empty)
+    }
+
+    /// Send the request, unless the cache holds a fresh copy or the server
+    /// answers it has not been modified
+    async fn send(&self, cache: Option<&ResponseCache>) -> Result<Fetched, FetchError> {
+        let target = &self.0.full_url();
+        let mut meta = FetchMeta::start(Local::now());
+        let cached = match cache {
+            Some(cache) => cache.load(target).await,
+            None => None,
+        };
+        if let (Some(cache), Some(entry)) = (cache, cached.as_ref()) {
+            if cache.is_fresh(entry, Local::now()) {
+                println!("Serving {} from response cache", target);
+                meta.cached = true;
+                return Ok(Fetched::Cached(entry.clone(), meta));
+            }
+        }
+        let host = reqwest::Url::parse(target)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+            .unwrap_or_default();
+        CIRCUIT_BREAKER.check(&host)?;
+        RATE_LIMITER.acquire(&host).await;
+
+        let crawling = match crawling_client(&host).await {
+            Ok(crawling) => crawling,
+            Err(e) => {
+                CIRCUIT_BREAKER.record_failure(&host);
+                return Err(e);
+            }
+        };
+        let proxy = crawling.proxy;
+        meta.proxy = proxy.map(|entry| entry.name.clone());
+        let method = match self.0.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Post => reqwest::Method::POST,
+        };
+        let mut request = crawling.client.request(method, target);
+        for (name, value) in self.0.headers.iter() {
+            request = request.header(name, value);
+        }
+        request = match &self.0.body {
+            RequestBody::Empty => request,
+            RequestBody::Form(fields) => request.form(fields),
+            RequestBody::Json(value) => request.json(value),
+        };
+        if let Some(entry) = cached.as_ref() {
+            if let Some(etag) = &entry.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let attempts = Attempts::default();
+        let result = request.with_extension(attempts.clone()).send().await;
+        meta.retries = attempts.retries();
+        if let Some(proxy) = proxy {
+            PROXY_POOL.record(proxy, &result);
+        }
+        match &result {
+            Ok(resp)
+                if !resp.status().is_server_error()
+                    && resp.status() != StatusCode::TOO_MANY_REQUESTS =>
+            {
+                CIRCUIT_BREAKER.record_success(&host)
+            }
+            _ => CIRCUIT_BREAKER.record_failure(&host),
+        }
+
+        let resp = result.map_err(|e| FetchError::from_request(e, proxy.is_some()))?;
+        meta.record_response(resp.status().as_u16(), resp.headers(), Local::now());
+        if let Some(session) = &crawling.session {
+            if matches!(
+                resp.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            ) {
+                // the server dropped the session, warm up again next time
+                session.invalidate().await;
+            }
+        }
+        if let Some(reason) = resp.headers().get(BLOCKED_HEADER) {
+            return Err(FetchError::Throttled(format!(
+                "{} ({})",
+                String::from_utf8_lossy(reason.as_bytes()),
+                target
+            )));
+        }
+
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            if let (Some(cache), Some(entry)) = (cache, cached) {
+                println!("{} not modified, serving cached response", target);
+                if let Err(e) = cache.touch(&entry, Local::now()).await {
+                    eprintln!("Failed to refresh cached response {}: {}", target, e);
+                }
+                meta.cached = true;
+                return Ok(Fetched::Cached(entry, meta));
+            }
+        }
+
+        match resp.status() {
+            StatusCode::OK => Ok(Fetched::Response(resp, meta)),
+            StatusCode::NOT_FOUND => Err(FetchError::NotFound(target.to_owned())),
+            StatusCode::TOO_MANY_REQUESTS => Err(FetchError::Throttled(format!(
+                "{} answered {}",
+                target,
+                resp.status()
+            ))),
+            status => Err(FetchError::Http(status)),
+        }
+    }
+
+    fn get_content_type(&self, headers: &HeaderMap) -> String {
+        match headers.get("content-type") {
+            Some(header_value) => match header_value.to_str() {
+                Ok(value) => value.to_owned(),
+                Err(_) => String::new(), // Handle the error case, e.g., log the error
+            },
+            None => String::new(), // Handle the case where the header is not present
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> Fetch for FileFetcher<'a> {
+    type Error = FetchError;
+
+    async fn fetch(&self) -> Result<Payload, Self::Error> {
+        single(self.0, self.fetch_all().await?)
+    }
+
+    async fn fetch_all(&self) -> Result<Vec<Payload>, Self::Error> {
+        let path = scheme::path_of(self.0);
+        let body = fs::read(path).await?;
+
+        // archived bodies keep their original source next to them
+        if let Ok(entry) = ArchiveEntry::read(path).await {
+            return entry.restore(body);
+        }
+
+        let name = path.to_string_lossy();
+        let content_type = if name.ends_with(".gz") {
+            ""
+        } else {
+            content_type_for(&name)
+        };
+        let meta = FetchMeta::local(&body);
+        decode_documents(self.0, &name, content_type, body, &meta)
+    }
+
+    async fn fetch_stream(&self) -> Result<PayloadStream, Self::Error> {
+        let path = scheme::path_of(self.0);
+        let mut body: ByteStream = Box::pin(ReaderStream::new(fs::File::open(path).await?));
+        let head = stream::read_head(&mut body, SNIFF_LEN).await?;
+
+        // archived bodies carry their metadata aside, compressed files need
+        // inflating, both take the buffered path
+        if compression::is_compressed(&head) || ArchiveEntry::read(path).await.is_ok() {
+            return Ok(PayloadStream::from(self.fetch().await?));
+        }
+
+        let content_type = content_type_for(&path.to_string_lossy());
+        Ok(PayloadStream::decode(
+            self.0,
+            content_type,
+            head,
+            body,
+            FetchMeta::default(),
+            SETTINGS.decoding.lossy,
+        ))
+    }
+}
+
+// Testcases for fetch_content, using mock on http and files
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_content_http() {
+        // Request a new server from the pool
+        let mut server = mockito::Server::new_async().await;
+
+        // Use one of these addresses to configure your client
+        let url = server.url();
+        let mock = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("Hello World")
+            .create_async()
+            .await;
+
+        let payload = fetch_content(url.as_str()).await.unwrap();
+        assert_eq!(payload.content, "Hello World");
+        assert_eq!(payload.source, url);
+        assert_eq!(payload.content_type, "text/html");
+        assert_eq!(payload.request, Some(FetchRequest::get(url.as_str())));
+        assert_eq!(payload.meta.status, Some(200));
+        assert_eq!(payload.meta.size, 11);
+        assert_eq!(payload.meta.retries, 0);
+        assert!(payload.meta.latency_ms.is_some());
+        assert!(payload
+            .meta
+            .headers
+            .contains(&("content-type".to_string(), "text/html".to_string())));
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_content_post_form() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/mops/web/ajax_t05st10_ifrs")
+            .match_query(mockito::Matcher::UrlEncoded("step".into(), "1".into()))
+            .match_header("content-type", "application/x-www-form-urlencoded")
+            .match_body("co_id=2330&year=113")
+            .with_status(200)
+            .with_header("content-type", "text/html; charset=utf-8")
+            .with_body("<table>營業收入</table>")
+            .create_async()
+            .await;
+
+        let request = FetchRequest {
+            method: Method::Post,
+            url: format!("{}/mops/web/ajax_t05st10_ifrs", server.url()),
+            query: vec![("step".to_string(), "1".to_string())],
+            body: RequestBody::Form(vec![
+                ("co_id".to_string(), "2330".to_string()),
+                ("year".to_string(), "113".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let payload = fetch_content(request.clone()).await.unwrap();
+        assert_eq!(payload.content, "<table>營業收入</table>");
+        assert_eq!(payload.source, request.full_url());
+        assert_eq!(payload.request, Some(request));
+
+        mock.assert_async().await;
+
+        let post_file = FetchRequest {
+            method: Method::Post,
+            ..FetchRequest::get("file://Cargo.toml")
+        };
+        assert!(matches!(
+            fetch_content(post_file).await,
+            Err(FetchError::Unsupported(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_stream_http() {
+        use tokio::io::AsyncReadExt;
+
+        // larger than the sniffed head, so the rest arrives as a stream
+        let rows = "2330,台積電\n".repeat(2000);
+        let (body, _, _) = encoding_rs::BIG5.encode(&rows);
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/MI_INDEX.csv")
+            .with_status(200)
+            .with_header("content-type", "text/csv")
+            .with_body(body.as_ref())
+            .create_async()
+            .await;
+
+        let url = format!("{}/MI_INDEX.csv", server.url());
+        let mut stream = fetch_stream(url.as_str()).await.unwrap();
+        assert_eq!(stream.source, url);
+        assert_eq!(stream.request, Some(FetchRequest::get(url.as_str())));
+        assert!(stream.meta().finished_at.is_none());
+
+        let mut content = String::new();
+        stream.read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, rows);
+
+        let meta = stream.meta();
+        assert_eq!(meta.status, Some(200));
+        assert_eq!(meta.size, body.len());
+        assert_eq!(meta.sha256, FetchMeta::local(&body).sha256);
+        assert!(meta.finished_at.is_some());
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_content_not_found() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/holiday")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let url = format!("{}/holiday", server.url());
+        let result = fetch_content(url.as_str()).await;
+        assert!(matches!(result, Err(FetchError::NotFound(source)) if source == url));
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_content_file() {
+        let payload = fetch_content("file://Cargo.toml").await.unwrap();
+        assert!(payload.content.contains("version"));
+        assert_eq!(payload.source, "file://Cargo.toml");
+        assert_eq!(payload.content_type, "text/plain");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_content_compressed() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all("證券代號,證券名稱".as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/MI_INDEX.csv.gz")
+            .with_status(200)
+            .with_header("content-type", "application/gzip")
+            .with_body(&gzipped)
+            .create_async()
+            .await;
+        let url = format!("{}/MI_INDEX.csv.gz", server.url());
+        let payload = fetch_content(url.as_str()).await.unwrap();
+        assert_eq!(payload.content, "證券代號,證券名稱");
+        assert_eq!(payload.content_type, "text/csv");
+        mock.assert_async().await;
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        writer.start_file("twse.csv", options).unwrap();
+        writer.write_all(b"2330,TSMC").unwrap();
+        writer.start_file("tpex.csv", options).unwrap();
+        writer.write_all(b"6488,GlobalWafers").unwrap();
+        let zipped = writer.finish().unwrap().into_inner();
+
+        let path = std::env::temp_dir().join(format!("ultron-bundle-{}.zip", std::process::id()));
+        fs::write(&path, zipped).await.unwrap();
+        let source = format!("file://{}", path.display());
+        let payloads = fetch_all_content(&source).await.unwrap();
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(payloads[0].source, "twse.csv");
+        assert_eq!(payloads[1].content, "6488,GlobalWafers");
+        assert!(matches!(
+            fetch_content(&source).await,
+            Err(FetchError::Unsupported(_))
+        ));
+        fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,232 @@
+use reqwest::{Response, StatusCode};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::setting::{ProxySelection, ProxySettings};
+
+/// One upstream proxy with its health record
+pub struct ProxyEntry {
+    pub name: String,
+    pub proxy: reqwest::Proxy,
+    failures: AtomicU32,
+    quarantined_until: Mutex<Option<Instant>>,
+}
+
+impl ProxyEntry {
+    fn quarantined_until(&self) -> Option<Instant> {
+        *self
+            .quarantined_until
+            .lock()
+            .expect("Proxy quarantine lock poisoned")
+    }
+
+    fn is_available(&self, now: Instant) -> bool {
+        self.quarantined_until().is_none_or(|until| until <= now)
+    }
+}
+
+/// Rotates requests over the configured proxies, skipping the ones that got
+/// blocked (403/429) or could not be connected to until their cooldown is over.
+pub struct ProxyPool {
+    entries: Vec<ProxyEntry>,
+    selection: ProxySelection,
+    cooldown: Duration,
+    no_proxy_hosts: Vec<String>,
+    cursor: AtomicUsize,
+}
+
+impl ProxyPool {
+    pub fn new(settings: &ProxySettings) -> Self {
+        let entries = settings
+            .servers
+            .iter()
+            .filter_map(
+                |server| match reqwest::Proxy::all(server.connection_string()) {
+                    Ok(proxy) => Some(ProxyEntry {
+                        name: server.name(),
+                        proxy,
+                        failures: AtomicU32::new(0),
+                        quarantined_until: Mutex::new(None),
+                    }),
+                    Err(e) => {
+                        eprintln!("Skipped malformed proxy {}: {}", server.name(), e);
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        Self {
+            entries,
+            selection: settings.selection,
+            cooldown: Duration::from_secs(settings.cooldown_secs),
+            no_proxy_hosts: settings.no_proxy_hosts.clone(),
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pick the proxy for a host, `None` means the request goes out directly
+    pub fn select(&self, host: &str) -> Option<&ProxyEntry> {
+        if self.entries.is_empty() || self.bypass(host) {
+            return None;
+        }
+
+        let now = Instant::now();
+        let available: Vec<&ProxyEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.is_available(now))
+            .collect();
+        if available.is_empty() {
+            // every proxy is cooling down, take the one recovering first
+            return self
+                .entries
+                .iter()
+                .min_by_key(|entry| entry.quarantined_until());
+        }
+
+        match self.selection {
+            ProxySelection::RoundRobin => {
+                let index = self.cursor.fetch_add(1, Ordering::Relaxed) % available.len();
+                Some(available[index])
+            }
+            ProxySelection::LeastFailures => available
+                .into_iter()
+                .min_by_key(|entry| entry.failures.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Update the proxy health from the outcome of a request sent through it
+    pub fn record(&self, entry: &ProxyEntry, result: &reqwest_middleware::Result<Response>) {
+        let blocked = match result {
+            Ok(resp) => matches!(
+                resp.status(),
+                StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+            ),
+            // a failure of the target site says nothing about the proxy, while
+            // one refusing or stalling the connection is unusable
+            Err(reqwest_middleware::Error::Reqwest(e)) => e.is_connect() || e.is_timeout(),
+            Err(reqwest_middleware::Error::Middleware(_)) => false,
+        };
+        if blocked {
+            self.quarantine(entry);
+        }
+    }
+
+    fn quarantine(&self, entry: &ProxyEntry) {
+        let failures = entry.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        *entry
+            .quarantined_until
+            .lock()
+            .expect("Proxy quarantine lock poisoned") = Some(Instant::now() + self.cooldown);
+        eprintln!(
+            "Proxy {} quarantined for {}s ({} failures)",
+            entry.name,
+            self.cooldown.as_secs(),
+            failures
+        );
+    }
+
+    fn bypass(&self, host: &str) -> bool {
+        // a proxy cannot reach services on this machine
+        if ["localhost", "127.0.0.1", "[::1]"].contains(&host) {
+            return true;
+        }
+        self.no_proxy_hosts
+            .iter()
+            .any(|no_proxy| host == no_proxy || host.ends_with(&format!(".{}", no_proxy)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::setting::ProxyServer;
+
+    fn server(host: &str) -> ProxyServer {
+        ProxyServer {
+            scheme: "socks5".to_string(),
+            host: host.to_string(),
+            port: "1080".to_string(),
+            username: String::new(),
+            passwd_env: "PROXY_PASSWD".to_string(),
+            passwd: String::new(),
+        }
+    }
+
+    fn pool(selection: ProxySelection) -> ProxyPool {
+        let settings = ProxySettings {
+            servers: vec![server("proxy-a"), server("proxy-b")],
+            selection,
+            cooldown_secs: 60,
+            no_proxy_hosts: vec!["twse.com.tw".to_string()],
+        };
+        ProxyPool::new(&settings)
+    }
+
+    #[test]
+    fn test_select_round_robin() {
+        let pool = pool(ProxySelection::RoundRobin);
+        let first = pool.select("fubon-ebrokerdj.fbs.com.tw").unwrap();
+        let second = pool.select("fubon-ebrokerdj.fbs.com.tw").unwrap();
+        assert_ne!(first.name, second.name);
+    }
+
+    #[test]
+    fn test_select_skips_quarantined() {
+        let pool = pool(ProxySelection::LeastFailures);
+        let blocked = pool.select("fubon-ebrokerdj.fbs.com.tw").unwrap();
+        pool.quarantine(blocked);
+
+        for _ in 0..4 {
+            let entry = pool.select("fubon-ebrokerdj.fbs.com.tw").unwrap();
+            assert_ne!(entry.name, blocked.name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_quarantines_on_connect_errors_only() {
+        let pool = pool(ProxySelection::LeastFailures);
+        let entry = &pool.entries[0];
+
+        let refused = reqwest::get("http://127.0.0.1:1").await.unwrap_err();
+        assert!(refused.is_connect());
+        pool.record(entry, &Err(reqwest_middleware::Error::Reqwest(refused)));
+        assert_eq!(entry.failures.load(Ordering::Relaxed), 1);
+
+        let other = reqwest_middleware::Error::Middleware(anyhow::anyhow!("block page"));
+        pool.record(&pool.entries[1], &Err(other));
+        assert_eq!(pool.entries[1].failures.load(Ordering::Relaxed), 0);
+        assert!(pool.entries[1].is_available(Instant::now()));
+    }
+
+    #[test]
+    fn test_select_no_proxy_hosts() {
+        let pool = pool(ProxySelection::RoundRobin);
+        assert!(pool.select("www.twse.com.tw").is_none());
+        assert!(pool.select("twse.com.tw").is_none());
+        assert!(pool.select("127.0.0.1").is_none());
+        assert!(pool.select("www.tpex.org.tw").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_record_quarantines_on_timeouts() {
+        let pool = pool(ProxySelection::LeastFailures);
+        let entry = &pool.entries[0];
+
+        // accepts the connection but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let stalled = client.get(url).send().await.unwrap_err();
+        assert!(stalled.is_timeout());
+
+        pool.record(entry, &Err(reqwest_middleware::Error::Reqwest(stalled)));
+        assert_eq!(entry.failures.load(Ordering::Relaxed), 1);
+        assert!(!entry.is_available(Instant::now()));
+    }
+}
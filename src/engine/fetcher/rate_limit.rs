@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::setting::RateLimitSettings;
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token-bucket limiter keyed by host, every host owns its own bucket so a slow
+/// upstream never throttles requests sent to another one.
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        Self {
+            settings,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait until the bucket of `host` allows one more request
+    pub async fn acquire(&self, host: &str) {
+        let wait = self.reserve(host);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take one token from the bucket and return how long the caller has to wait
+    /// before using it. Tokens may go negative, which queues callers in order.
+    fn reserve(&self, host: &str) -> Duration {
+        let limit = self.settings.for_host(host);
+        if limit.requests_per_second <= 0.0 {
+            // rate limiting disabled for this host
+            return Duration::ZERO;
+        }
+
+        let burst = f64::from(limit.burst.max(1));
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("Rate limiter lock poisoned");
+        let bucket = buckets.entry(host.to_owned()).or_insert(Bucket {
+            tokens: burst,
+            last_refill: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.requests_per_second).min(burst);
+        bucket.last_refill = now;
+        bucket.tokens -= 1.0;
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / limit.requests_per_second)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::setting::HostRateLimit;
+
+    fn settings() -> RateLimitSettings {
+        let mut hosts = HashMap::new();
+        hosts.insert(
+            "www.twse.com.tw".to_string(),
+            HostRateLimit {
+                requests_per_second: 1.0,
+                burst: 2,
+            },
+        );
+        RateLimitSettings {
+            default: HostRateLimit {
+                requests_per_second: 0.0,
+                burst: 0,
+            },
+            hosts,
+        }
+    }
+
+    #[test]
+    fn test_reserve_within_burst() {
+        let limiter = RateLimiter::new(settings());
+        assert!(limiter.reserve("www.twse.com.tw").is_zero());
+        assert!(limiter.reserve("www.twse.com.tw").is_zero());
+
+        let wait = limiter.reserve("www.twse.com.tw");
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_reserve_disabled_host() {
+        let limiter = RateLimiter::new(settings());
+        for _ in 0..100 {
+            assert!(limiter.reserve("www.tpex.org.tw").is_zero());
+        }
+    }
+}
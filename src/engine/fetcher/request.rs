@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Method {
+    #[default]
+    Get,
+    Post,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestBody {
+    #[default]
+    Empty,
+    /// `application/x-www-form-urlencoded` fields, in order
+    Form(Vec<(String, String)>),
+    Json(serde_json::Value),
+}
+
+/// Everything needed to issue a request, recorded on the payload it produced
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FetchRequest {
+    #[serde(default)]
+    pub method: Method,
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub query: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: RequestBody,
+    /// Charset the body is decoded as, whatever the response declares
+    #[serde(default)]
+    pub charset: Option<String>,
+}
+
+impl FetchRequest {
+    pub fn get(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    /// A bare GET, which any URI scheme can serve
+    pub fn is_plain_get(&self) -> bool {
+        self.method == Method::Get
+            && self.headers.is_empty()
+            && self.query.is_empty()
+            && self.body == RequestBody::Empty
+    }
+
+    /// Content type a response is decoded as, `declared` unless a charset is forced
+    pub fn content_type(&self, declared: String) -> String {
+        match &self.charset {
+            Some(charset) => {
+                let mime = declared.split(';').next().unwrap_or_default().trim();
+                format!("{}; charset={}", mime, charset)
+            }
+            None => declared,
+        }
+    }
+
+    /// URL with the query parameters appended
+    pub fn full_url(&self) -> String {
+        match reqwest::Url::parse(&self.url) {
+            Ok(mut url) if !self.query.is_empty() => {
+                url.query_pairs_mut().extend_pairs(&self.query);
+                url.to_string()
+            }
+            _ => self.url.clone(),
+        }
+    }
+}
+
+impl From<&str> for FetchRequest {
+    fn from(url: &str) -> Self {
+        Self::get(url)
+    }
+}
+
+impl From<String> for FetchRequest {
+    fn from(url: String) -> Self {
+        Self::get(url)
+    }
+}
+
+impl From<&String> for FetchRequest {
+    fn from(url: &String) -> Self {
+        Self::get(url.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_url() {
+        let request = FetchRequest {
+            query: vec![
+                ("date".to_string(), "20240723".to_string()),
+                ("type".to_string(), "ALLBUT0999".to_string()),
+            ],
+            ..FetchRequest::get("https://www.twse.com.tw/rwd/zh/afterTrading/MI_INDEX")
+        };
+        assert_eq!(
+            request.full_url(),
+            "https://www.twse.com.tw/rwd/zh/afterTrading/MI_INDEX?date=20240723&type=ALLBUT0999"
+        );
+        assert!(!request.is_plain_get());
+        assert!(FetchRequest::from("file://Cargo.toml").is_plain_get());
+    }
+
+    #[test]
+    fn test_forced_charset() {
+        let request = FetchRequest {
+            charset: Some("big5".to_string()),
+            ..FetchRequest::get("https://www.twse.com.tw/rwd/zh/fund/MI_QFIIS")
+        };
+        assert_eq!(
+            request.content_type("text/csv; charset=utf-8".to_string()),
+            "text/csv; charset=big5"
+        );
+        assert_eq!(
+            FetchRequest::get("https://www.twse.com.tw").content_type("text/csv".to_string()),
+            "text/csv"
+        );
+    }
+
+    #[test]
+    fn test_deserialize_form_request() {
+        let request: FetchRequest = serde_json::from_str(
+            r#"{
+                "method": "POST",
+                "url": "https://mops.twse.com.tw/mops/web/ajax_t05st10_ifrs",
+                "body": {"form": [["co_id", "2330"], ["year", "113"]]}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(
+            request.body,
+            RequestBody::Form(vec![
+                ("co_id".to_string(), "2330".to_string()),
+                ("year".to_string(), "113".to_string()),
+            ])
+        );
+        assert!(request.headers.is_empty());
+    }
+}
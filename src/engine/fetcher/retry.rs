@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use http::Extensions;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Error, Middleware, Next};
+use reqwest_retry::{default_on_request_failure, Retryable, RetryableStrategy};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Retries connection errors, timeouts and the configured HTTP statuses
+pub struct StatusRetryStrategy {
+    statuses: Vec<u16>,
+}
+
+impl StatusRetryStrategy {
+    pub fn new(statuses: &[u16]) -> Self {
+        Self {
+            statuses: statuses.to_vec(),
+        }
+    }
+}
+
+impl RetryableStrategy for StatusRetryStrategy {
+    fn handle(&self, res: &Result<reqwest::Response, Error>) -> Option<Retryable> {
+        match res {
+            Ok(resp) if self.statuses.contains(&resp.status().as_u16()) => {
+                Some(Retryable::Transient)
+            }
+            Ok(resp) if resp.status().is_success() => None,
+            Ok(_) => Some(Retryable::Fatal),
+            Err(error) => default_on_request_failure(error),
+        }
+    }
+}
+
+/// Attempts made for one request, shared with the caller through the request
+/// extensions since the retry middleware keeps its count to itself
+#[derive(Debug, Clone, Default)]
+pub struct Attempts(Arc<AtomicU32>);
+
+impl Attempts {
+    pub fn retries(&self) -> u32 {
+        self.0.load(Ordering::Relaxed).saturating_sub(1)
+    }
+}
+
+/// Counts every attempt, placed right after the retry middleware
+pub struct AttemptCounterMiddleware;
+
+#[async_trait]
+impl Middleware for AttemptCounterMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        if let Some(attempts) = extensions.get::<Attempts>() {
+            attempts.0.fetch_add(1, Ordering::Relaxed);
+        }
+        next.run(req, extensions).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16) -> Result<reqwest::Response, Error> {
+        Ok(reqwest::Response::from(
+            http::Response::builder().status(status).body("").unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_configured_statuses() {
+        let strategy = StatusRetryStrategy::new(&[429, 503]);
+        // Retryable implements no Debug
+        assert!(strategy.handle(&response(429)) == Some(Retryable::Transient));
+        assert!(strategy.handle(&response(503)) == Some(Retryable::Transient));
+        assert!(strategy.handle(&response(500)) == Some(Retryable::Fatal));
+        assert!(strategy.handle(&response(200)).is_none());
+    }
+}
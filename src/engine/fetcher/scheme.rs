@@ -0,0 +1,204 @@
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+
+use super::{
+    decode_documents, single, Fetch, FetchError, FetchMeta, FetchRequest, FileFetcher, Payload,
+    UrlFetcher,
+};
+
+/// Builds the fetcher of one source string
+pub type FetcherFactory =
+    Arc<dyn for<'a> Fn(&'a str) -> Box<dyn Fetch<Error = FetchError> + 'a> + Send + Sync + 'static>;
+
+lazy_static! {
+    static ref SCHEMES: RwLock<HashMap<String, FetcherFactory>> = RwLock::new(builtin_schemes());
+}
+
+fn builtin_schemes() -> HashMap<String, FetcherFactory> {
+    let mut schemes: HashMap<String, FetcherFactory> = HashMap::new();
+    let url: FetcherFactory = Arc::new(|source| Box::new(UrlFetcher(FetchRequest::get(source))));
+    schemes.insert("http".to_string(), url.clone());
+    schemes.insert("https".to_string(), url);
+    schemes.insert(
+        "stdin".to_string(),
+        Arc::new(|source| Box::new(StdinFetcher(source))),
+    );
+    schemes.insert(
+        "dir".to_string(),
+        Arc::new(|source| Box::new(DirFetcher(source))),
+    );
+    // compressed files are recognised by their magic bytes, so gzip:// and
+    // zip:// are plain aliases of file://
+    let file: FetcherFactory = Arc::new(|source| Box::new(FileFetcher(source)));
+    for scheme in ["file", "gzip", "gz", "zip"] {
+        schemes.insert(scheme.to_string(), file.clone());
+    }
+    schemes
+}
+
+/// Register the fetcher of a URI scheme, replacing the built-in one if any
+// extension point for downstream sources, nothing in the binary registers one
+#[allow(dead_code)]
+pub fn register_scheme<F>(scheme: &str, factory: F)
+where
+    F: for<'a> Fn(&'a str) -> Box<dyn Fetch<Error = FetchError> + 'a> + Send + Sync + 'static,
+{
+    SCHEMES
+        .write()
+        .expect("Scheme registry lock poisoned")
+        .insert(scheme.to_ascii_lowercase(), Arc::new(factory));
+}
+
+/// Find the fetcher for a source, `-` being stdin
+pub fn resolve(source: &str) -> Result<Box<dyn Fetch<Error = FetchError> + '_>, FetchError> {
+    let scheme = if source == "-" {
+        "stdin".to_string()
+    } else {
+        source
+            .split_once("://")
+            .map(|(scheme, _)| scheme.to_ascii_lowercase())
+            .ok_or_else(|| FetchError::Unsupported(source.to_owned()))?
+    };
+
+    let factory = SCHEMES
+        .read()
+        .expect("Scheme registry lock poisoned")
+        .get(&scheme)
+        .cloned()
+        .ok_or_else(|| FetchError::Unsupported(source.to_owned()))?;
+    Ok(factory(source))
+}
+
+/// Path part of a `<scheme>://<path>` source
+pub(super) fn path_of(source: &str) -> &Path {
+    Path::new(source.split_once("://").map_or(source, |(_, path)| path))
+}
+
+/// Reads the whole standard input, `-`
+pub struct StdinFetcher<'a>(pub &'a str);
+
+#[async_trait]
+impl Fetch for StdinFetcher<'_> {
+    type Error = FetchError;
+
+    async fn fetch(&self) -> Result<Payload, Self::Error> {
+        single(self.0, self.fetch_all().await?)
+    }
+
+    async fn fetch_all(&self) -> Result<Vec<Payload>, Self::Error> {
+        let mut bytes = Vec::new();
+        tokio::io::stdin().read_to_end(&mut bytes).await?;
+        let meta = FetchMeta::local(&bytes);
+        decode_documents(self.0, "", "text/plain", bytes, &meta)
+    }
+}
+
+/// Every regular file of `dir://<path>`, in name order
+pub struct DirFetcher<'a>(pub &'a str);
+
+#[async_trait]
+impl Fetch for DirFetcher<'_> {
+    type Error = FetchError;
+
+    async fn fetch(&self) -> Result<Payload, Self::Error> {
+        single(self.0, self.fetch_all().await?)
+    }
+
+    async fn fetch_all(&self) -> Result<Vec<Payload>, Self::Error> {
+        let mut files: Vec<PathBuf> = Vec::new();
+        let mut entries = fs::read_dir(path_of(self.0)).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            // metadata sidecars of archived bodies are not payloads
+            let sidecar = path.extension().is_some_and(|ext| ext == "json")
+                && path.with_extension("body").exists();
+            // nor are hidden files, e.g. bodies still being archived
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if entry.file_type().await?.is_file() && !sidecar && !hidden {
+                files.push(path);
+            }
+        }
+        files.sort();
+
+        let mut payloads = Vec::with_capacity(files.len());
+        for file in files {
+            let source = format!("file://{}", file.display());
+            payloads.extend(FileFetcher(&source).fetch_all().await?);
+        }
+        Ok(payloads)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StaticFetcher<'a>(&'a str);
+
+    #[async_trait]
+    impl Fetch for StaticFetcher<'_> {
+        type Error = FetchError;
+
+        async fn fetch(&self) -> Result<Payload, Self::Error> {
+            Ok(Payload {
+                content: "stored".to_string(),
+                source: self.0.to_owned(),
+                content_type: "text/plain".to_string(),
+                date: None,
+                request: None,
+                meta: FetchMeta::default(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_scheme() {
+        register_scheme("objstore", |source| Box::new(StaticFetcher(source)));
+
+        let payload = resolve("objstore://bucket/key")
+            .unwrap()
+            .fetch()
+            .await
+            .unwrap();
+        assert_eq!(payload.content, "stored");
+        assert_eq!(payload.source, "objstore://bucket/key");
+    }
+
+    #[test]
+    fn test_resolve_unsupported() {
+        assert!(matches!(
+            resolve("ftp://host/file"),
+            Err(FetchError::Unsupported(_))
+        ));
+        assert!(matches!(resolve("abc"), Err(FetchError::Unsupported(_))));
+    }
+
+    #[tokio::test]
+    async fn test_dir_fetch_all() {
+        let dir = std::env::temp_dir().join(format!("ultron-dir-{}", std::process::id()));
+        fs::create_dir_all(&dir).await.unwrap();
+        fs::write(dir.join("a.csv"), "2330,TSMC").await.unwrap();
+        fs::write(dir.join("b.csv"), "6488,GlobalWafers")
+            .await
+            .unwrap();
+        // a body still being archived is left out
+        fs::write(dir.join(".c.partial"), "1101,TCC").await.unwrap();
+
+        let source = format!("dir://{}", dir.display());
+        let payloads = resolve(&source).unwrap().fetch_all().await.unwrap();
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(payloads[0].content, "2330,TSMC");
+        assert_eq!(
+            payloads[1].source,
+            format!("file://{}", dir.join("b.csv").display())
+        );
+        assert!(resolve(&source).unwrap().fetch().await.is_err());
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use reqwest::cookie::Jar;
+use reqwest_middleware::ClientWithMiddleware;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::FetchError;
+use crate::config::setting::{SessionProfile, SessionSettings};
+
+/// Client with its own cookie jar, warmed up on a landing page before use
+pub struct Session {
+    pub client: ClientWithMiddleware,
+    // tokio mutex so concurrent requests wait for a single warm-up
+    warmed_at: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl Session {
+    pub fn new(client: ClientWithMiddleware) -> Self {
+        Self {
+            client,
+            warmed_at: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Visit the warm-up URL when the session is new or older than its TTL
+    pub async fn warm_up(
+        &self,
+        profile: &SessionProfile,
+        via_proxy: bool,
+    ) -> Result<(), FetchError> {
+        let mut warmed_at = self.warmed_at.lock().await;
+        let ttl = Duration::from_secs(profile.ttl_secs);
+        if warmed_at.is_some_and(|at| at.elapsed() < ttl) {
+            return Ok(());
+        }
+
+        println!("Warming up session on {}", profile.warm_up_url);
+        let resp = self
+            .client
+            .get(&profile.warm_up_url)
+            .send()
+            .await
+            .map_err(|e| FetchError::from_request(e, via_proxy))?;
+        if !resp.status().is_success() {
+            return Err(FetchError::Http(resp.status()));
+        }
+
+        *warmed_at = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Force a new warm-up, e.g. once the server rejected the session
+    pub async fn invalidate(&self) {
+        *self.warmed_at.lock().await = None;
+    }
+}
+
+/// Session-scoped clients of the hosts declaring a warm-up URL, one session per
+/// host and proxy since cookies are usually bound to the client address.
+pub struct SessionPool {
+    settings: SessionSettings,
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+}
+
+impl SessionPool {
+    pub fn new(settings: &SessionSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn profile(&self, host: &str) -> Option<&SessionProfile> {
+        self.settings.hosts.get(host)
+    }
+
+    /// Session of a host through a proxy, built with a fresh cookie jar
+    pub fn session(
+        &self,
+        host: &str,
+        proxy: Option<&str>,
+        build_client: impl FnOnce(Arc<Jar>) -> ClientWithMiddleware,
+    ) -> Arc<Session> {
+        let key = format!("{}|{}", host, proxy.unwrap_or("direct"));
+        self.sessions
+            .lock()
+            .expect("Session pool lock poisoned")
+            .entry(key)
+            .or_insert_with(|| {
+                let jar = Arc::new(Jar::default());
+                Arc::new(Session::new(build_client(jar)))
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest_middleware::ClientBuilder;
+
+    fn client(jar: Arc<Jar>) -> ClientWithMiddleware {
+        let client = reqwest::Client::builder()
+            .cookie_provider(jar)
+            .build()
+            .unwrap();
+        ClientBuilder::new(client).build()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_warm_up_sets_cookie() {
+        let mut server = mockito::Server::new_async().await;
+        let landing = server
+            .mock("GET", "/mops/web/index")
+            .with_status(200)
+            .with_header("set-cookie", "jcsession=abc123; Path=/")
+            .expect(1)
+            .create_async()
+            .await;
+        let data = server
+            .mock("GET", "/mops/web/t05st01")
+            .match_header("cookie", "jcsession=abc123")
+            .with_status(200)
+            .with_body("ok")
+            .create_async()
+            .await;
+
+        let pool = SessionPool::new(&SessionSettings {
+            hosts: HashMap::from([(
+                "mops.twse.com.tw".to_string(),
+                SessionProfile {
+                    warm_up_url: format!("{}/mops/web/index", server.url()),
+                    ttl_secs: 1800,
+                },
+            )]),
+        });
+        let profile = pool.profile("mops.twse.com.tw").unwrap();
+        let session = pool.session("mops.twse.com.tw", None, client);
+
+        // the second warm-up is skipped while the session is fresh
+        session.warm_up(profile, false).await.unwrap();
+        session.warm_up(profile, false).await.unwrap();
+        let resp = session
+            .client
+            .get(format!("{}/mops/web/t05st01", server.url()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.text().await.unwrap(), "ok");
+
+        assert!(Arc::ptr_eq(
+            &session,
+            &pool.session("mops.twse.com.tw", None, client)
+        ));
+        landing.assert_async().await;
+        data.assert_async().await;
+    }
+}
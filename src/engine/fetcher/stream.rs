@@ -0,0 +1,357 @@
+use bytes::{Bytes, BytesMut};
+use chrono::Local;
+use encoding_rs::Decoder;
+use futures::stream::{self, Stream, StreamExt};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio_util::io::{StreamReader, SyncIoBridge};
+
+use super::{charset, FetchMeta, FetchRequest, Payload};
+
+/// Chunks of a body as they arrive
+pub type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// Takes every chunk of a body, then `None` once it is complete
+type Sink = Box<dyn FnMut(Option<&[u8]>) + Send>;
+
+// how much of a body is read up front to pick its encoding or spot a block page
+pub const SNIFF_LEN: usize = 8 * 1024;
+
+/// Payload whose body is decoded into UTF-8 chunk by chunk while it is read,
+/// so large files never sit in memory as a whole.
+pub struct PayloadStream {
+    pub source: String,
+    pub content_type: String,
+    pub date: Option<String>,
+    pub request: Option<FetchRequest>,
+    /// Completed with the size and hash of the raw body once it is read through
+    pub(super) meta: Arc<Mutex<FetchMeta>>,
+    /// Sinks of the raw body, handed its chunks as they are decoded
+    sinks: Arc<Mutex<Vec<Sink>>>,
+    body: StreamReader<ByteStream, Bytes>,
+}
+
+impl PayloadStream {
+    /// Decode a raw body, `head` being its first bytes already read from `rest`
+    pub fn decode(
+        source: &str,
+        content_type: &str,
+        head: Bytes,
+        rest: ByteStream,
+        meta: FetchMeta,
+        lossy: bool,
+    ) -> Self {
+        let encoding = charset::detect(content_type, &head);
+        let mut decoder = encoding.new_decoder_with_bom_removal();
+        let source_name = source.to_owned();
+        let meta = Arc::new(Mutex::new(meta));
+        let sinks: Arc<Mutex<Vec<Sink>>> = Arc::default();
+        let raw = Box::pin(stream::once(async move { Ok(head) }).chain(rest));
+        let counted = meta.clone();
+        let mut tally = tally(meta.clone());
+        let teed = sinks.clone();
+        let body = tee(raw, move |chunk| {
+            tally(chunk);
+            feed(&teed, chunk);
+        })
+        .map(Some)
+        // a final None flushes the decoder
+        .chain(stream::once(async { None }))
+        .map(move |chunk| decode_chunk(&mut decoder, chunk, lossy, &source_name, &counted));
+
+        Self {
+            source: source.to_owned(),
+            content_type: content_type.to_owned(),
+            date: None,
+            request: None,
+            meta,
+            sinks,
+            body: StreamReader::new(Box::pin(body)),
+        }
+    }
+
+    /// Payload decoded as a whole, e.g. once inflated, `raw` being the body it
+    /// was decoded from
+    pub fn buffered(payload: Payload, raw: Bytes) -> Self {
+        let content = Bytes::from(payload.content);
+        let sinks: Arc<Mutex<Vec<Sink>>> = Arc::default();
+        let teed = sinks.clone();
+        // the raw body goes to the sinks once the content has been read
+        let end = stream::once(async move {
+            feed(&teed, Some(&raw));
+            feed(&teed, None);
+        })
+        .filter_map(|_| async { None });
+        Self {
+            source: payload.source,
+            content_type: payload.content_type,
+            date: payload.date,
+            request: payload.request,
+            meta: Arc::new(Mutex::new(payload.meta)),
+            sinks,
+            body: StreamReader::new(Box::pin(
+                stream::once(async move { Ok(content) }).chain(end),
+            )),
+        }
+    }
+
+    /// Metadata of the fetch, lacking the size and hash of a streamed body
+    /// until it has been read through
+    pub fn meta(&self) -> FetchMeta {
+        self.meta
+            .lock()
+            .expect("Fetch metadata lock poisoned")
+            .clone()
+    }
+
+    /// Hand every raw chunk of the body to `sink` as it is read, then `None`
+    /// once the body is complete
+    pub fn tee(self, sink: impl FnMut(Option<&[u8]>) + Send + 'static) -> Self {
+        self.sinks
+            .lock()
+            .expect("Payload sinks lock poisoned")
+            .push(Box::new(sink));
+        self
+    }
+
+    /// Blocking reader for `csv::Reader` and friends, to be consumed inside
+    /// `tokio::task::spawn_blocking`
+    pub fn into_sync_reader(self) -> SyncIoBridge<Self> {
+        SyncIoBridge::new(self)
+    }
+}
+
+/// Payload whose raw body is no longer at hand, its content standing in for it
+impl From<Payload> for PayloadStream {
+    fn from(payload: Payload) -> Self {
+        let raw = Bytes::from(payload.content.clone());
+        Self::buffered(payload, raw)
+    }
+}
+
+impl AsyncRead for PayloadStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().body).poll_read(cx, buf)
+    }
+}
+
+fn decode_chunk(
+    decoder: &mut Decoder,
+    chunk: Option<io::Result<Bytes>>,
+    lossy: bool,
+    source: &str,
+    meta: &Mutex<FetchMeta>,
+) -> io::Result<Bytes> {
+    let (src, last) = match chunk {
+        Some(chunk) => (chunk?, false),
+        None => (Bytes::new(), true),
+    };
+
+    let capacity = decoder
+        .max_utf8_buffer_length(src.len())
+        .unwrap_or(src.len() * 3 + 16);
+    let mut decoded = String::with_capacity(capacity);
+    let (_, _, replaced) = decoder.decode_to_string(&src, &mut decoded, last);
+    if replaced {
+        if !lossy {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("malformed sequences decoding {}", source),
+            ));
+        }
+        meta.lock()
+            .expect("Fetch metadata lock poisoned")
+            .replacements += decoded.matches('\u{FFFD}').count();
+    }
+
+    Ok(Bytes::from(decoded))
+}
+
+fn feed(sinks: &Mutex<Vec<Sink>>, chunk: Option<&[u8]>) {
+    for sink in sinks
+        .lock()
+        .expect("Payload sinks lock poisoned")
+        .iter_mut()
+    {
+        sink(chunk);
+    }
+}
+
+/// Sink sizing and hashing a raw body, recorded on `meta` once it completed
+fn tally(meta: Arc<Mutex<FetchMeta>>) -> impl FnMut(Option<&[u8]>) + Send + 'static {
+    let mut size = 0;
+    let mut hasher = Sha256::new();
+    move |chunk| match chunk {
+        Some(chunk) => {
+            size += chunk.len();
+            hasher.update(chunk);
+        }
+        None => meta
+            .lock()
+            .expect("Fetch metadata lock poisoned")
+            .finish_stream(size, std::mem::take(&mut hasher), Local::now()),
+    }
+}
+
+/// Hand every chunk of a stream to `sink`, then `None` once it completed
+/// without errors
+pub fn tee(body: ByteStream, mut sink: impl FnMut(Option<&[u8]>) + Send + 'static) -> ByteStream {
+    let mut failed = false;
+    Box::pin(
+        body.map(Some)
+            .chain(stream::once(async { None }))
+            .filter_map(move |chunk| {
+                let chunk = match chunk {
+                    Some(Ok(bytes)) => {
+                        sink(Some(&bytes));
+                        Some(Ok(bytes))
+                    }
+                    Some(Err(e)) => {
+                        failed = true;
+                        Some(Err(e))
+                    }
+                    None => {
+                        if !failed {
+                            sink(None);
+                        }
+                        None
+                    }
+                };
+                async move { chunk }
+            }),
+    )
+}
+
+/// Read up to `len` bytes off the front of a stream
+pub async fn read_head(body: &mut ByteStream, len: usize) -> io::Result<Bytes> {
+    let mut head = BytesMut::new();
+    while head.len() < len {
+        match body.next().await {
+            Some(chunk) => head.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    Ok(head.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::BIG5;
+    use std::io::Read;
+    use tokio::io::AsyncReadExt;
+
+    async fn read_all(mut stream: PayloadStream) -> io::Result<String> {
+        let mut content = String::new();
+        stream.read_to_string(&mut content).await?;
+        Ok(content)
+    }
+
+    fn chunked(bytes: &[u8], size: usize) -> ByteStream {
+        let chunks: Vec<io::Result<Bytes>> = bytes
+            .chunks(size)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        Box::pin(stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn test_decode_big5_across_chunks() {
+        let (body, _, _) = BIG5.encode("證券代號,證券名稱\n2330,台積電\n");
+        // odd chunk sizes split double-byte characters
+        let mut raw = chunked(&body, 3);
+        let head = read_head(&mut raw, 4).await.unwrap();
+        let stream = PayloadStream::decode(
+            "file://MI_INDEX.csv",
+            "text/csv",
+            head,
+            raw,
+            FetchMeta::default(),
+            false,
+        );
+        let meta = stream.meta.clone();
+
+        assert_eq!(
+            read_all(stream).await.unwrap(),
+            "證券代號,證券名稱\n2330,台積電\n"
+        );
+        // the raw body is accounted for once read through
+        let meta = meta.lock().unwrap();
+        assert_eq!(meta.size, body.len());
+        assert_eq!(meta.sha256, FetchMeta::local(&body).sha256);
+        assert!(meta.finished_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_decode_rejects_malformed() {
+        let mut body = BIG5.encode("台積電").0.into_owned();
+        body.push(0xFF);
+        let stream = PayloadStream::decode(
+            "file://MI_INDEX.csv",
+            "text/csv; charset=big5",
+            Bytes::new(),
+            chunked(&body, 2),
+            FetchMeta::default(),
+            false,
+        );
+        assert!(read_all(stream).await.is_err());
+
+        // leniently decoded, the replacements are counted instead
+        let stream = PayloadStream::decode(
+            "file://MI_INDEX.csv",
+            "text/csv; charset=big5",
+            Bytes::new(),
+            chunked(&body, 2),
+            FetchMeta::default(),
+            true,
+        );
+        let meta = stream.meta.clone();
+        assert_eq!(read_all(stream).await.unwrap(), "台積電\u{FFFD}");
+        assert_eq!(meta.lock().unwrap().replacements, 1);
+    }
+
+    #[tokio::test]
+    async fn test_sync_reader_and_tee() {
+        let (body, _, _) = BIG5.encode("2330,台積電\n6488,環球晶\n");
+        let teed = std::sync::Arc::new(std::sync::Mutex::new((Vec::new(), false)));
+        let sink = teed.clone();
+        let stream = PayloadStream::decode(
+            "file://a.csv",
+            "text/csv",
+            Bytes::new(),
+            chunked(&body, 5),
+            FetchMeta::default(),
+            false,
+        )
+        .tee(move |chunk| {
+            let mut teed = sink.lock().unwrap();
+            match chunk {
+                Some(bytes) => teed.0.extend_from_slice(bytes),
+                None => teed.1 = true,
+            }
+        });
+
+        let mut reader = stream.into_sync_reader();
+        let records = tokio::task::spawn_blocking(move || {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).unwrap();
+            text
+        })
+        .await
+        .unwrap();
+        assert_eq!(records, "2330,台積電\n6488,環球晶\n");
+
+        // the sink gets the body as received, not as decoded
+        let teed = teed.lock().unwrap();
+        assert_eq!(teed.0, body.as_ref());
+        assert!(teed.1);
+    }
+}
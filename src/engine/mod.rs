@@ -1,4 +1,8 @@
+pub mod columns;
+pub mod dataset;
 pub mod fetcher;
 pub mod models;
 pub mod parser;
+pub mod schema;
+pub mod security;
 pub mod strategies;
@@ -35,6 +35,63 @@ impl Concentration {
     }
 }
 
+/// Side of the top 15 ranking a broker branch is listed on
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// Broker branch among the top 15 net buyers or sellers of a concentration page
+#[derive(Debug, Serialize)]
+pub struct BrokerFlow {
+    #[serde(rename = "stockId")]
+    pub stock_id: String,
+
+    #[serde(rename = "exchangeDate")]
+    pub exchange_date: String,
+
+    /// Page of the ranking, at the same position as in `Concentration::concentration`
+    pub period: usize,
+
+    pub side: Side,
+
+    /// Position within its side, from 1
+    pub rank: usize,
+
+    /// Securities firm, the `BHID` of the branch link
+    #[serde(rename = "brokerId")]
+    pub broker_id: String,
+
+    /// Branch code, hex encoded as `b` in the branch link
+    #[serde(rename = "branchId")]
+    pub branch_id: String,
+
+    #[serde(rename = "brokerName")]
+    pub broker_name: String,
+
+    #[serde(rename = "buyShares")]
+    pub buy_shares: i32,
+
+    #[serde(rename = "sellShares")]
+    pub sell_shares: i32,
+
+    /// Bought minus sold, negative for net sellers
+    #[serde(rename = "netShares")]
+    pub net_shares: i32,
+
+    /// Share of the traded volume, in percent
+    #[serde(rename = "volumeRatio")]
+    pub volume_ratio: f32,
+}
+
+impl BrokerFlow {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self)
+    }
+}
+
 #[derive(Debug)]
 pub struct Temp(
     pub String,
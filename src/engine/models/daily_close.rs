@@ -1,6 +1,64 @@
 use serde::Serialize;
 use serde_json;
+use std::collections::HashMap;
 
+use crate::engine::columns::{Column, IndexSet};
+
+const COLUMNS: &[Column] = &[
+    Column {
+        key: "stock_id",
+        names: &["證券代號", "代號"],
+        required: true,
+    },
+    Column {
+        key: "trade_shares",
+        names: &["成交股數"],
+        required: true,
+    },
+    Column {
+        key: "transactions",
+        names: &["成交筆數"],
+        required: true,
+    },
+    Column {
+        key: "turnover",
+        names: &["成交金額", "成交金額(元)"],
+        required: true,
+    },
+    Column {
+        key: "open",
+        names: &["開盤價", "開盤"],
+        required: true,
+    },
+    Column {
+        key: "high",
+        names: &["最高價", "最高"],
+        required: true,
+    },
+    Column {
+        key: "low",
+        names: &["最低價", "最低"],
+        required: true,
+    },
+    Column {
+        key: "close",
+        names: &["收盤價", "收盤"],
+        required: true,
+    },
+    Column {
+        key: "diff",
+        names: &["漲跌價差", "漲跌"],
+        required: true,
+    },
+    // TWSE reports the sign of the difference in a column of its own
+    Column {
+        key: "diff_sign",
+        names: &["漲跌(+/-)"],
+        required: false,
+    },
+];
+
+#[derive(Debug, PartialEq)]
 pub struct CsvIndexSet {
     pub stock_id: usize,
     pub trade_shares: usize,
@@ -14,8 +72,25 @@ pub struct CsvIndexSet {
     pub diff_sign: Option<usize>,
 }
 
-impl CsvIndexSet {
-    pub fn new_twse() -> Self {
+impl IndexSet for CsvIndexSet {
+    const COLUMNS: &'static [Column] = COLUMNS;
+
+    fn from_positions(positions: &HashMap<&'static str, usize>) -> Self {
+        Self {
+            stock_id: positions["stock_id"],
+            trade_shares: positions["trade_shares"],
+            transactions: positions["transactions"],
+            turnover: positions["turnover"],
+            open: positions["open"],
+            high: positions["high"],
+            low: positions["low"],
+            close: positions["close"],
+            diff: positions["diff"],
+            diff_sign: positions.get("diff_sign").copied(),
+        }
+    }
+
+    fn new_twse() -> Self {
         Self {
             stock_id: 0,
             trade_shares: 2,
@@ -30,7 +105,7 @@ impl CsvIndexSet {
         }
     }
 
-    pub fn new_tpex() -> Self {
+    fn new_tpex() -> Self {
         Self {
             stock_id: 0,
             trade_shares: 7,
@@ -44,6 +119,34 @@ impl CsvIndexSet {
             diff_sign: None,
         }
     }
+
+    fn stock_id(&self) -> usize {
+        self.stock_id
+    }
+
+    // halted securities have no prices, while their volumes are zero
+    fn values(&self) -> Vec<usize> {
+        vec![self.open, self.high, self.low, self.close, self.diff]
+    }
+
+    fn width(&self) -> usize {
+        [
+            self.stock_id,
+            self.trade_shares,
+            self.transactions,
+            self.turnover,
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.diff,
+            self.diff_sign.unwrap_or_default(),
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or_default()
+            + 1
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -90,6 +193,47 @@ impl DailyClose {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use csv::StringRecord;
+
+    #[test]
+    fn test_index_set_from_header() {
+        let twse = StringRecord::from(vec![
+            "證券代號",
+            "證券名稱",
+            "成交股數",
+            "成交筆數",
+            "成交金額",
+            "開盤價",
+            "最高價",
+            "最低價",
+            "收盤價",
+            "漲跌(+/-)",
+            "漲跌價差",
+            "最後揭示買價",
+        ]);
+        assert_eq!(
+            CsvIndexSet::from_header(&twse),
+            Some(CsvIndexSet::new_twse())
+        );
+
+        let tpex = StringRecord::from(vec![
+            "代號",
+            "名稱",
+            "收盤 ",
+            "漲跌",
+            "開盤 ",
+            "最高 ",
+            "最低",
+            "成交股數  ",
+            " 成交金額(元)",
+            " 成交筆數 ",
+            "最後買價",
+        ]);
+        assert_eq!(
+            CsvIndexSet::from_header(&tpex),
+            Some(CsvIndexSet::new_tpex())
+        );
+    }
 
     #[test]
     fn test_model_to_json() {
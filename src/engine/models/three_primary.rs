@@ -1,6 +1,38 @@
 use serde::Serialize;
 use serde_json;
+use std::collections::HashMap;
 
+use crate::engine::columns::{Column, IndexSet};
+
+const COLUMNS: &[Column] = &[
+    Column {
+        key: "stock_id",
+        names: &["證券代號", "代號"],
+        required: true,
+    },
+    Column {
+        key: "foreign_trade_shares",
+        names: &["外陸資買賣超股數(不含外資自營商)", "外資及陸資-買賣超股數"],
+        required: true,
+    },
+    Column {
+        key: "trust_trade_shares",
+        names: &["投信買賣超股數", "投信-買賣超股數"],
+        required: true,
+    },
+    Column {
+        key: "dealer_trade_shares",
+        names: &["自營商買賣超股數(自行買賣)", "自營商(自行買賣)-買賣超股數"],
+        required: true,
+    },
+    Column {
+        key: "hedging_trade_shares",
+        names: &["自營商買賣超股數(避險)", "自營商(避險)-買賣超股數"],
+        required: true,
+    },
+];
+
+#[derive(Debug, PartialEq)]
 pub struct CsvIndexSet {
     pub stock_id: usize,
     pub foreign_trade_shares: usize,
@@ -9,8 +41,20 @@ pub struct CsvIndexSet {
     pub hedging_trade_shares: usize,
 }
 
-impl CsvIndexSet {
-    pub fn new_twse() -> Self {
+impl IndexSet for CsvIndexSet {
+    const COLUMNS: &'static [Column] = COLUMNS;
+
+    fn from_positions(positions: &HashMap<&'static str, usize>) -> Self {
+        Self {
+            stock_id: positions["stock_id"],
+            foreign_trade_shares: positions["foreign_trade_shares"],
+            trust_trade_shares: positions["trust_trade_shares"],
+            dealer_trade_shares: positions["dealer_trade_shares"],
+            hedging_trade_shares: positions["hedging_trade_shares"],
+        }
+    }
+
+    fn new_twse() -> Self {
         Self {
             stock_id: 0,
             foreign_trade_shares: 4,
@@ -20,7 +64,7 @@ impl CsvIndexSet {
         }
     }
 
-    pub fn new_tpex() -> Self {
+    fn new_tpex() -> Self {
         Self {
             stock_id: 0,
             foreign_trade_shares: 10,
@@ -29,6 +73,28 @@ impl CsvIndexSet {
             hedging_trade_shares: 19,
         }
     }
+
+    fn stock_id(&self) -> usize {
+        self.stock_id
+    }
+
+    fn values(&self) -> Vec<usize> {
+        vec![
+            self.foreign_trade_shares,
+            self.trust_trade_shares,
+            self.dealer_trade_shares,
+            self.hedging_trade_shares,
+        ]
+    }
+
+    fn width(&self) -> usize {
+        self.values()
+            .into_iter()
+            .chain([self.stock_id])
+            .max()
+            .unwrap_or_default()
+            + 1
+    }
 }
 
 #[derive(Debug, Serialize)]
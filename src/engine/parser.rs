@@ -1,8 +1,13 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use std::fmt::Display;
+use csv::StringRecord;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
 use std::str::FromStr;
 
+// offending rows kept per rejection reason
+const MAX_SAMPLES: usize = 3;
+
 #[derive(Debug)]
 pub struct Parser<T: ParseStrategy> {
     strategy: T,
@@ -38,3 +43,86 @@ pub trait Conversion {
             .map_err(|e| anyhow!("Failed to parse {}: {}", without_comma, e))
     }
 }
+
+/// Records parsed from a document along with how its rows were handled
+#[derive(Debug)]
+pub struct Parsed<T> {
+    pub records: Vec<T>,
+    pub report: ParseReport,
+}
+
+/// Rows seen by a strategy, accepted or rejected by reason, so a short output
+/// tells halted stocks apart from a broken parser
+#[derive(Debug, Default)]
+pub struct ParseReport {
+    pub total: usize,
+    pub accepted: usize,
+    pub rejected: BTreeMap<&'static str, Rejections>,
+}
+
+#[derive(Debug, Default)]
+pub struct Rejections {
+    pub count: usize,
+    /// First offending rows, fields joined by commas
+    pub samples: Vec<String>,
+}
+
+impl ParseReport {
+    pub fn accept(&mut self) {
+        self.total += 1;
+        self.accepted += 1;
+    }
+
+    pub fn reject(&mut self, reason: &'static str, record: &StringRecord) {
+        self.total += 1;
+        let rejections = self.rejected.entry(reason).or_default();
+        rejections.count += 1;
+        if rejections.samples.len() < MAX_SAMPLES {
+            rejections
+                .samples
+                .push(record.iter().collect::<Vec<_>>().join(","));
+        }
+    }
+}
+
+impl Display for ParseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rows, {} accepted, {} rejected",
+            self.total,
+            self.accepted,
+            self.total - self.accepted
+        )?;
+        for (reason, rejections) in self.rejected.iter() {
+            write!(f, "\n  {}: {}", reason, rejections.count)?;
+            for sample in rejections.samples.iter() {
+                write!(f, "\n    {}", sample)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_report() {
+        let mut report = ParseReport::default();
+        report.accept();
+        for code in ["9999", "9998", "9997", "9996"] {
+            report.reject("no price", &StringRecord::from(vec![code, "--"]));
+        }
+        report.reject("not a security code", &StringRecord::from(vec!["備註:"]));
+
+        assert_eq!(report.total, 6);
+        assert_eq!(report.rejected["no price"].count, 4);
+        assert_eq!(report.rejected["no price"].samples.len(), MAX_SAMPLES);
+        assert_eq!(
+            report.to_string(),
+            "6 rows, 1 accepted, 5 rejected\n  no price: 4\n    9999,--\n    9998,--\n    9997,--\n  not a security code: 1\n    備註:"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use lazy_static::lazy_static;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::config::setting::{SchemaSettings, SETTINGS};
+use crate::engine::columns;
+use crate::engine::strategies::rwd;
+
+lazy_static! {
+    // unit tests parse trimmed down fixtures, never the recorded layouts
+    pub static ref SCHEMAS: Option<Schemas> = (SETTINGS.schema.enabled
+        && !cfg!(any(test, feature = "testing")))
+    .then(|| Schemas::new(&SETTINGS.schema));
+}
+
+/// Layout of an upstream document, as far as strategies rely on it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fingerprint {
+    /// Normalized header row of a CSV export
+    pub headers: Vec<String>,
+    /// Matches of the selectors a strategy reads from
+    pub selectors: BTreeMap<String, usize>,
+}
+
+impl Fingerprint {
+    pub fn csv(header: &StringRecord) -> Self {
+        Self {
+            headers: header
+                .iter()
+                .map(columns::normalize)
+                .filter(|name| !name.is_empty())
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn html(document: &Html, selectors: &[&str]) -> Result<Self> {
+        Self::element(document.root_element(), selectors)
+    }
+
+    /// Fingerprint of the part of a page below `element`, e.g. one table row
+    pub fn element(element: ElementRef, selectors: &[&str]) -> Result<Self> {
+        let count = |selector: &str| -> Result<usize> {
+            let parsed = Selector::parse(selector)
+                .map_err(|e| anyhow!("Failed to create selector {}: {}", selector, e))?;
+            Ok(element.select(&parsed).count())
+        };
+
+        Ok(Self {
+            selectors: selectors
+                .iter()
+                .map(|selector| Ok((selector.to_string(), count(selector)?)))
+                .collect::<Result<_>>()?,
+            ..Default::default()
+        })
+    }
+
+    /// What changed from `self` to `actual`, one line per change
+    pub fn diff(&self, actual: &Fingerprint) -> Vec<String> {
+        let mut changes = Vec::new();
+        for (index, name) in self.headers.iter().enumerate() {
+            match actual.headers.iter().position(|other| other == name) {
+                None => changes.push(format!("column \"{}\" removed", name)),
+                Some(moved) if moved != index => changes.push(format!(
+                    "column \"{}\" moved from {} to {}",
+                    name, index, moved
+                )),
+                Some(_) => {}
+            }
+        }
+        for (index, name) in actual.headers.iter().enumerate() {
+            if !self.headers.contains(name) {
+                changes.push(format!("column \"{}\" added at {}", name, index));
+            }
+        }
+
+        for (selector, expected) in self.selectors.iter() {
+            let found = actual.selectors.get(selector).copied().unwrap_or_default();
+            if found != *expected {
+                changes.push(format!(
+                    "selector \"{}\" matched {} -> {}",
+                    selector, expected, found
+                ));
+            }
+        }
+
+        changes
+    }
+}
+
+/// Upstream layout no longer matching its expected fingerprint
+#[derive(Debug)]
+pub struct SchemaDrift {
+    pub layout: String,
+    pub source: String,
+    pub changes: Vec<String>,
+}
+
+impl fmt::Display for SchemaDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Layout {} of {} changed:", self.layout, self.source)?;
+        for change in self.changes.iter() {
+            write!(f, "\n  - {}", change)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SchemaDrift {}
+
+/// Expected fingerprints, stored as `<root>/<layout>.json`. A layout without
+/// one fails like a drifted one unless recording is enabled. Strategies call
+/// it from blocking parse tasks, hence the synchronous IO.
+#[derive(Debug, Clone)]
+pub struct Schemas {
+    root: PathBuf,
+    record: bool,
+}
+
+impl Schemas {
+    pub fn new(settings: &SchemaSettings) -> Self {
+        Self {
+            root: PathBuf::from(&settings.path),
+            record: settings.record,
+        }
+    }
+
+    fn path(&self, layout: &str) -> PathBuf {
+        self.root.join(format!("{}.json", layout))
+    }
+
+    /// Stored fingerprint of `layout`, `None` when none is
+    pub fn expected(&self, layout: &str) -> Result<Option<Fingerprint>> {
+        match std::fs::read(self.path(layout)) {
+            Ok(stored) => Ok(Some(serde_json::from_slice(&stored)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Fail with a `SchemaDrift` when the layout of `source` changed
+    pub fn check(&self, layout: &str, source: &str, actual: &Fingerprint) -> Result<()> {
+        let path = self.path(layout);
+        let expected = match self.expected(layout)? {
+            Some(expected) => expected,
+            None if !self.record => {
+                return Err(SchemaDrift {
+                    layout: layout.to_owned(),
+                    source: source.to_owned(),
+                    changes: vec![format!("no fingerprint expected in {}", path.display())],
+                }
+                .into());
+            }
+            None => {
+                std::fs::create_dir_all(&self.root)?;
+                std::fs::write(&path, serde_json::to_vec_pretty(actual)?)?;
+                println!("Recorded layout {} in {}", layout, path.display());
+                return Ok(());
+            }
+        };
+
+        let changes = expected.diff(actual);
+        if changes.is_empty() {
+            return Ok(());
+        }
+        Err(SchemaDrift {
+            layout: layout.to_owned(),
+            source: source.to_owned(),
+            changes,
+        }
+        .into())
+    }
+}
+
+/// Layout name of a dataset as served by the exchange or broker of `source`,
+/// JSON renditions being layouts of their own
+pub fn layout(dataset: &str, source: &str) -> String {
+    let provider = ["twse", "tpex", "fbs"]
+        .into_iter()
+        .find(|provider| source.contains(provider))
+        .unwrap_or("other");
+    if rwd::is_json(source) {
+        format!("{}.{}.json", dataset, provider)
+    } else {
+        format!("{}.{}", dataset, provider)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_records_then_detects_drift() {
+        let root = std::env::temp_dir().join(format!("ultron-schemas-{}", std::process::id()));
+        let mut schemas = Schemas {
+            root: root.clone(),
+            record: false,
+        };
+        let layout = layout(
+            "daily_close",
+            "https://www.twse.com.tw/exchangeReport/MI_INDEX",
+        );
+        assert_eq!(layout, "daily_close.twse");
+
+        let expected = Fingerprint::csv(&StringRecord::from(vec![
+            "證券代號",
+            "證券名稱",
+            "成交股數",
+            "開盤價",
+            "",
+        ]));
+        // unknown layouts only get recorded on demand
+        let error = schemas.check(&layout, "twse", &expected).unwrap_err();
+        assert!(error.downcast_ref::<SchemaDrift>().is_some());
+        schemas.record = true;
+        schemas.check(&layout, "twse", &expected).unwrap();
+        schemas.record = false;
+        schemas.check(&layout, "twse", &expected).unwrap();
+
+        let actual = Fingerprint::csv(&StringRecord::from(vec![
+            "證券代號",
+            "證券名稱",
+            "成交股數",
+            "漲停價",
+            "開盤價",
+        ]));
+        let error = schemas.check(&layout, "twse", &actual).unwrap_err();
+        let drift = error.downcast_ref::<SchemaDrift>().unwrap();
+        assert_eq!(
+            drift.changes,
+            vec![
+                "column \"開盤價\" moved from 3 to 4",
+                "column \"漲停價\" added at 3",
+            ]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_html_fingerprint() {
+        let document = Html::parse_document(
+            r#"<table class="hasBorder"><tr><td class="t3n1" colspan=4>2,108</td></tr></table>"#,
+        );
+        let fingerprint =
+            Fingerprint::html(&document, &["table.hasBorder", "td.t3n1[colspan='4']"]).unwrap();
+        assert_eq!(fingerprint.selectors["td.t3n1[colspan='4']"], 1);
+
+        let empty =
+            Fingerprint::html(&Html::parse_document("<p></p>"), &["table.hasBorder"]).unwrap();
+        assert_eq!(
+            fingerprint.diff(&empty),
+            vec![
+                "selector \"table.hasBorder\" matched 1 -> 0",
+                "selector \"td.t3n1[colspan='4']\" matched 1 -> 0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+lazy_static! {
+    // ordered from the most specific grammar, the first match wins
+    static ref GRAMMAR: Vec<(Regex, SecurityClass)> = [
+        // 00 prefix with four to six characters, B marks bond funds
+        (r"^00\d{2,3}[0-9A-Z]?B$", SecurityClass::BondEtf),
+        (r"^00\d{2,4}[A-Z]?$", SecurityClass::Etf),
+        (r"^02\d{4}$", SecurityClass::Etn),
+        // TWSE warrants start with 03 to 08, TPEx ones with 7, puts end in P
+        (r"^(0[3-8]|7\d)\d{3}[0-9A-Z]$", SecurityClass::Warrant),
+        // TDRs take two or four digits after 91, e.g. 9103 or 911608
+        (r"^91(\d{2}){1,2}$", SecurityClass::Tdr),
+        (r"^[1-9]\d{3}$", SecurityClass::CommonStock),
+        (r"^[1-9]\d{3}[A-Z]$", SecurityClass::Preferred),
+    ]
+    .into_iter()
+    .map(|(pattern, class)| (Regex::new(pattern).unwrap(), class))
+    .collect();
+}
+
+/// Kind of security listed on TWSE or TPEx, told apart by its code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityClass {
+    CommonStock,
+    Etf,
+    BondEtf,
+    Preferred,
+    Warrant,
+    /// Taiwan depositary receipt
+    Tdr,
+    Etn,
+}
+
+impl SecurityClass {
+    /// Classify a security code, `None` when it follows no known grammar
+    pub fn of(code: &str) -> Option<Self> {
+        let code = code.trim();
+        GRAMMAR
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(code))
+            .map(|(_, class)| *class)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_codes() {
+        let cases = [
+            ("2330", Some(SecurityClass::CommonStock)),
+            ("0050", Some(SecurityClass::Etf)),
+            ("00878", Some(SecurityClass::Etf)),
+            ("006208", Some(SecurityClass::Etf)),
+            ("00632R", Some(SecurityClass::Etf)),
+            ("00679B", Some(SecurityClass::BondEtf)),
+            ("2881A", Some(SecurityClass::Preferred)),
+            ("9103", Some(SecurityClass::Tdr)),
+            ("911608", Some(SecurityClass::Tdr)),
+            ("91160", None),
+            ("020011", Some(SecurityClass::Etn)),
+            ("030001", Some(SecurityClass::Warrant)),
+            ("03001P", Some(SecurityClass::Warrant)),
+            ("71234P", Some(SecurityClass::Warrant)),
+            (" 6488 ", Some(SecurityClass::CommonStock)),
+            ("備註:", None),
+            ("123", None),
+        ];
+        for (code, class) in cases {
+            assert_eq!(SecurityClass::of(code), class, "{}", code);
+        }
+    }
+}
@@ -1,11 +1,27 @@
 use crate::engine::fetcher;
 use crate::engine::models::*;
 use crate::engine::parser::*;
+use crate::engine::schema::{self, Fingerprint, SCHEMAS};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use lazy_static::lazy_static;
 use regex::Regex;
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
+use std::sync::Arc;
+
+// buy and sell totals, then average buy and sell prices
+const TOTALS: &str = "td.t3n1[colspan='4']";
+// name of a broker branch, followed by its buy, sell, net and volume ratio
+const BROKERS: &str = "td.t4t1";
+const FIGURES: &str = "td.t3n1";
+// ranking rows, below them footer rows hold the totals
+const RANKS: &str = "table.hasBorder tr:not(#oScrollFoot)";
+
+lazy_static! {
+    // e.g. /z/zc/zco/zco0/zco0.djhtm?a=2330&b=0035003300380045&BHID=5380
+    static ref BRANCH_LINK: Regex = Regex::new(r"[?&]b=([0-9A-Fa-f]+)&BHID=(\w+)").unwrap();
+}
 
 #[derive(Debug)]
 pub struct ConcentrationStrategy;
@@ -33,7 +49,8 @@ impl ConcentrationStrategy {
 #[async_trait]
 impl ParseStrategy for ConcentrationStrategy {
     type Error = anyhow::Error;
-    type Input = fetcher::Payload;
+    /// Shared with `BrokerStrategy`, both reading the same page
+    type Input = Arc<fetcher::Payload>;
     type Output = concentration::Temp;
 
     async fn parse(&self, payload: Self::Input) -> Result<Self::Output, Self::Error> {
@@ -43,8 +60,13 @@ impl ParseStrategy for ConcentrationStrategy {
             payload.source, payload.content_type
         );
         let document = Html::parse_document(&payload.content);
-        let selector = Selector::parse("td.t3n1[colspan='4']")
-            .map_err(|e| anyhow!("Failed to create selector: {}", e))?;
+        if let Some(schemas) = SCHEMAS.as_ref() {
+            let layout = schema::layout("concentration", &payload.source);
+            let fingerprint = Fingerprint::html(&document, &["table.hasBorder", TOTALS])?;
+            schemas.check(&layout, &payload.source, &fingerprint)?;
+        }
+        let selector =
+            Selector::parse(TOTALS).map_err(|e| anyhow!("Failed to create selector: {}", e))?;
 
         let values = document
             .select(&selector)
@@ -72,16 +94,143 @@ impl ParseStrategy for ConcentrationStrategy {
     }
 }
 
+/// Reads the top 15 buying and selling broker branches of a concentration page
+#[derive(Debug)]
+pub struct BrokerStrategy;
+
+impl Conversion for BrokerStrategy {}
+
+impl BrokerStrategy {
+    /// Broker listed in `name`, `None` for the empty slots of a short ranking
+    /// and the footer labels, neither of which links to a branch
+    fn broker(
+        &self,
+        name: ElementRef,
+        side: concentration::Side,
+    ) -> Result<Option<concentration::BrokerFlow>> {
+        let Some(link) = name
+            .children()
+            .filter_map(ElementRef::wrap)
+            .find(|child| child.value().name() == "a")
+        else {
+            return Ok(None);
+        };
+        let href = link.value().attr("href").unwrap_or_default();
+        let captures = BRANCH_LINK
+            .captures(href)
+            .ok_or_else(|| anyhow!("Invalid branch link {}", href))?;
+
+        let values = name
+            .next_siblings()
+            .filter_map(ElementRef::wrap)
+            .take(4)
+            .map(|cell| cell.text().collect::<String>())
+            .collect::<Vec<_>>();
+        if values.len() != 4 {
+            return Err(anyhow!("Expected 4 values, found {}", values.len()));
+        }
+        let buy_shares = self.parse_with_comma::<i32>(&values[0])?;
+        let sell_shares = self.parse_with_comma::<i32>(&values[1])?;
+
+        Ok(Some(concentration::BrokerFlow {
+            stock_id: String::new(),
+            exchange_date: String::new(),
+            period: 0,
+            side,
+            rank: 0,
+            broker_id: captures[2].to_string(),
+            branch_id: self.branch_code(&captures[1])?,
+            broker_name: link.text().collect::<String>().trim().to_string(),
+            buy_shares,
+            sell_shares,
+            net_shares: buy_shares - sell_shares,
+            volume_ratio: self.parse_with_comma::<f32>(values[3].trim().trim_end_matches('%'))?,
+        }))
+    }
+
+    /// Cells of the first ranking row, a column added or moved changing them
+    fn fingerprint(&self, document: &Html) -> Result<Fingerprint> {
+        let ranks =
+            Selector::parse(RANKS).map_err(|e| anyhow!("Failed to create selector: {}", e))?;
+        let names =
+            Selector::parse(BROKERS).map_err(|e| anyhow!("Failed to create selector: {}", e))?;
+        match document
+            .select(&ranks)
+            .find(|row| row.select(&names).next().is_some())
+        {
+            Some(row) => Fingerprint::element(row, &["td", BROKERS, FIGURES]),
+            None => Ok(Fingerprint::default()),
+        }
+    }
+
+    /// Branch code spelled as UTF-16 code units in hex, `0035003300380045` being `538E`
+    fn branch_code(&self, hex: &str) -> Result<String> {
+        hex.as_bytes()
+            .chunks(4)
+            .map(|unit| {
+                std::str::from_utf8(unit)
+                    .ok()
+                    .and_then(|unit| u32::from_str_radix(unit, 16).ok())
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| anyhow!("Invalid branch code {}", hex))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ParseStrategy for BrokerStrategy {
+    type Error = anyhow::Error;
+    type Input = Arc<fetcher::Payload>;
+    type Output = Vec<concentration::BrokerFlow>;
+
+    async fn parse(&self, payload: Self::Input) -> Result<Self::Output, Self::Error> {
+        let (stock_id, pos) = ConcentrationStrategy.identifier(&payload.source)?;
+        let document = Html::parse_document(&payload.content);
+        if let Some(schemas) = SCHEMAS.as_ref() {
+            let layout = schema::layout("concentration_brokers", &payload.source);
+            schemas.check(&layout, &payload.source, &self.fingerprint(&document)?)?;
+        }
+        let rows = Selector::parse("table.hasBorder tr")
+            .map_err(|e| anyhow!("Failed to create selector: {}", e))?;
+        let names =
+            Selector::parse(BROKERS).map_err(|e| anyhow!("Failed to create selector: {}", e))?;
+
+        // every row lists a buying branch, then a selling one
+        let mut brokers = Vec::new();
+        let mut ranks = [0, 0];
+        for row in document.select(&rows) {
+            let sides = [concentration::Side::Buy, concentration::Side::Sell];
+            for (index, (name, side)) in row.select(&names).zip(sides).enumerate() {
+                if let Some(mut broker) = self.broker(name, side)? {
+                    ranks[index] += 1;
+                    broker.stock_id = stock_id.clone();
+                    broker.exchange_date = payload.date.clone().unwrap_or_default();
+                    broker.period = pos;
+                    broker.rank = ranks[index];
+                    brokers.push(broker);
+                }
+            }
+        }
+
+        Ok(brokers)
+    }
+}
+
 // Testcases for ConcentrationStrategy parse and to_i32, to_usize
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::setting::SchemaSettings;
+    use crate::engine::schema::{SchemaDrift, Schemas};
 
     #[tokio::test(flavor = "multi_thread")]
     async fn test_concentration_strategy_parse() {
         let strategy = ConcentrationStrategy {};
         let payload = fetcher::Payload {
             date: None,
+            request: None,
+            meta: fetcher::FetchMeta::default(),
             content_type: "text/html".to_string(),
             source: "https://fubon-ebrokerdj.fbs.com.tw/z/zc/zco/zco_2330_2.djhtm".to_string(),
             content: r##"<table class="hasBorder" width="100%" cellspacing="1" cellpadding="0" border="0" bgcolor="#F0F0F0"><TR>
@@ -118,7 +267,7 @@ mod tests {
             .to_string(),
         };
 
-        let result = strategy.parse(payload).await;
+        let result = strategy.parse(Arc::new(payload)).await;
         assert!(result.is_ok());
         let concentration = result.unwrap();
         assert_eq!(concentration.0, "2330");
@@ -130,6 +279,60 @@ mod tests {
         assert_eq!(concentration.6, 54.32);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_broker_strategy_parse() {
+        let payload = fetcher::Payload {
+            date: Some("20240723".to_string()),
+            request: None,
+            meta: fetcher::FetchMeta::default(),
+            content_type: "text/html".to_string(),
+            source: "https://fubon-ebrokerdj.fbs.com.tw/z/zc/zco/zco_2330_6.djhtm".to_string(),
+            content: r##"<table class="hasBorder"><TR>
+            <TD class="t4t1" nowrap><a href="/z/zc/zco/zco0/zco0.djhtm?a=2330&b=0035003300380045&BHID=5380">第一金-自由</a></TD>
+            <TD class="t3n1">1,034</TD>
+            <TD class="t3n1">8</TD>
+            <TD class="t3n1">1,026</TD>
+            <TD class="t3n1">12.32%</TD>
+            <TD class="t4t1" nowrap><a href="/z/zc/zco/zco0/zco0.djhtm?a=2330&b=0039003200300041&BHID=9200">凱基-板橋</a></TD>
+            <TD class="t3n1">2</TD>
+            <TD class="t3n1">36</TD>
+            <TD class="t3n1">34</TD>
+            <TD class="t3n1">0.42%</TD>
+            </TR><TR>
+            <TD class="t4t1" nowrap></TD>
+            <TD class="t3n1"></TD><TD class="t3n1"></TD><TD class="t3n1"></TD><TD class="t3n1"></TD>
+            <TD class="t4t1" nowrap><a href="/z/zc/zco/zco0/zco0.djhtm?a=2330&b=0031003400340030&BHID=1440">美林</a></TD>
+            <TD class="t3n1">0</TD>
+            <TD class="t3n1">20</TD>
+            <TD class="t3n1">20</TD>
+            <TD class="t3n1">0.25%</TD>
+            </TR><TR id="oScrollFoot">
+            <TD class="t4t1" nowrap>合計買超張數</td>
+            <td class="t3n1" colspan=4>1,026</td>
+            <TD class="t4t1" nowrap>合計賣超張數</td>
+            <td class="t3n1" colspan=4>54</td>
+            </TR></table>"##
+                .to_string(),
+        };
+
+        let brokers = BrokerStrategy.parse(Arc::new(payload)).await.unwrap();
+        assert_eq!(brokers.len(), 3);
+        assert_eq!(
+            brokers[0].to_json().unwrap(),
+            r#"{"stockId":"2330","exchangeDate":"20240723","period":4,"side":"buy","rank":1,"brokerId":"5380","branchId":"538E","brokerName":"第一金-自由","buyShares":1034,"sellShares":8,"netShares":1026,"volumeRatio":12.32}"#
+        );
+        assert_eq!(brokers[1].side, concentration::Side::Sell);
+        assert_eq!(brokers[1].net_shares, -34);
+        assert_eq!(
+            (
+                brokers[2].rank,
+                brokers[2].side,
+                brokers[2].branch_id.as_str()
+            ),
+            (2, concentration::Side::Sell, "1440")
+        );
+    }
+
     #[test]
     fn test_concentration_strategy_to_i32() {
         let strategy = ConcentrationStrategy {};
@@ -161,4 +364,40 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), 2);
     }
+
+    #[test]
+    fn test_broker_fingerprint() {
+        let schemas = Schemas::new(&SchemaSettings {
+            path: concat!(env!("CARGO_MANIFEST_DIR"), "/schemas").to_string(),
+            ..Default::default()
+        });
+        let source = "https://fubon-ebrokerdj.fbs.com.tw/z/zc/zco/zco_2330_1.djhtm";
+        let row = r#"<TD class="t4t1"><a href="?a=2330&b=0035003300380045&BHID=5380">第一金-自由</a></TD>
+            <TD class="t3n1">34</TD><TD class="t3n1">8</TD><TD class="t3n1">26</TD><TD class="t3n1">0.32%</TD>
+            <TD class="t4t1"><a href="?a=2330&b=0039003200300041&BHID=9200">凱基-板橋</a></TD>
+            <TD class="t3n1">2</TD><TD class="t3n1">36</TD><TD class="t3n1">34</TD><TD class="t3n1">0.42%</TD>"#;
+        let page = |row: &str| {
+            Html::parse_document(&format!(
+                r#"<table class="hasBorder"><TR>{}</TR><TR id="oScrollFoot"><TD class="t4t1">合計買超張數</TD></TR></table>"#,
+                row
+            ))
+        };
+
+        let fingerprint = BrokerStrategy.fingerprint(&page(row)).unwrap();
+        schemas
+            .check("concentration_brokers.fbs", source, &fingerprint)
+            .unwrap();
+
+        // an average price column after the net shares shifts the volume ratio
+        let drifted = row.replacen(
+            r#"<TD class="t3n1">0.32%</TD>"#,
+            r#"<TD class="t3n1">54.59</TD><TD class="t3n1">0.32%</TD>"#,
+            1,
+        );
+        let fingerprint = BrokerStrategy.fingerprint(&page(&drifted)).unwrap();
+        let error = schemas
+            .check("concentration_brokers.fbs", source, &fingerprint)
+            .unwrap_err();
+        assert!(error.downcast_ref::<SchemaDrift>().is_some());
+    }
 }
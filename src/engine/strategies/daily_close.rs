@@ -1,10 +1,17 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
 use csv::StringRecord;
+use std::io::Read;
 
+use crate::config::setting::SETTINGS;
+use crate::engine::columns::IndexSet;
 use crate::engine::fetcher;
 use crate::engine::models::*;
 use crate::engine::parser::*;
+use crate::engine::schema::{Schemas, SCHEMAS};
+use crate::engine::security::SecurityClass;
+
+use super::{rows, rwd};
 
 #[derive(Debug)]
 pub struct DailyCloseStrategy;
@@ -12,20 +19,6 @@ pub struct DailyCloseStrategy;
 impl Conversion for DailyCloseStrategy {}
 
 impl DailyCloseStrategy {
-    fn is_valid_record(&self, record: &StringRecord, index_set: &daily_close::CsvIndexSet) -> bool {
-        record.len() >= 17
-            && self.is_integer(&record[index_set.stock_id])
-            && [
-                index_set.open,
-                index_set.high,
-                index_set.low,
-                index_set.close,
-                index_set.diff,
-            ]
-            .iter()
-            .all(|&index| self.valid(&record[index]))
-    }
-
     fn parse_record(
         &self,
         record: &StringRecord,
@@ -39,7 +32,7 @@ impl DailyCloseStrategy {
         };
 
         Ok(daily_close::DailyClose {
-            stock_id: record[index_set.stock_id].to_string(),
+            stock_id: record[index_set.stock_id].trim().to_string(),
             exchange_date: date.clone().unwrap_or_default(),
             trade_shares: self.parse_with_comma::<i64>(&record[index_set.trade_shares])?,
             transactions: self.parse_with_comma::<i32>(&record[index_set.transactions])?,
@@ -52,43 +45,231 @@ impl DailyCloseStrategy {
         })
     }
 
-    fn is_integer(&self, s: &str) -> bool {
-        s.parse::<i32>().is_ok() && s.len() == 4
+    /// Parse the records of a CSV export as it is read
+    fn parse_records(
+        &self,
+        reader: impl Read,
+        source: &str,
+        schemas: Option<&Schemas>,
+        fallback: Option<daily_close::CsvIndexSet>,
+        allowed: &[SecurityClass],
+        date: &Option<String>,
+    ) -> Result<Parsed<daily_close::DailyClose>> {
+        rows::parse_rows(
+            rows::records(reader),
+            "daily_close",
+            source,
+            schemas,
+            fallback,
+            allowed,
+            |record, index_set| self.parse_record(record, index_set, date),
+        )
     }
+}
+
+#[async_trait]
+impl ParseStrategy for DailyCloseStrategy {
+    type Error = anyhow::Error;
+    type Input = fetcher::PayloadStream;
+    type Output = Parsed<daily_close::DailyClose>;
+
+    async fn parse(&self, payload: Self::Input) -> Result<Self::Output, Self::Error> {
+        let fallback = rows::fallback::<daily_close::CsvIndexSet>(&payload.source)?;
+
+        // the bridge needs the runtime, reading happens off it
+        let date = payload.date.clone();
+        let source = payload.source.clone();
+        let allowed = SETTINGS.securities.allowed("daily_close").to_vec();
+        let reader = payload.into_sync_reader();
+        tokio::task::spawn_blocking(move || {
+            DailyCloseStrategy.parse_records(
+                reader,
+                &source,
+                SCHEMAS.as_ref(),
+                fallback,
+                &allowed,
+                &date,
+            )
+        })
+        .await?
+    }
+}
+
+/// `DailyCloseStrategy` over the JSON rendition of the TWSE MI_INDEX report
+#[derive(Debug)]
+pub struct DailyCloseJsonStrategy;
+
+impl Conversion for DailyCloseJsonStrategy {}
 
-    fn valid(&self, s: &str) -> bool {
-        !s.is_empty() && s != "---"
+impl DailyCloseJsonStrategy {
+    /// Parse the rows of the table holding security codes
+    fn parse_table(
+        &self,
+        reader: impl Read,
+        source: &str,
+        schemas: Option<&Schemas>,
+        allowed: &[SecurityClass],
+        date: &Option<String>,
+    ) -> Result<Parsed<daily_close::DailyClose>> {
+        let table = rwd::table(reader, source, daily_close::CsvIndexSet::is_header)?;
+        rows::parse_rows(
+            table.into_iter().map(Ok),
+            "daily_close",
+            source,
+            schemas,
+            None,
+            allowed,
+            |record, index_set| DailyCloseStrategy.parse_record(record, index_set, date),
+        )
     }
 }
 
 #[async_trait]
-impl ParseStrategy for DailyCloseStrategy {
+impl ParseStrategy for DailyCloseJsonStrategy {
     type Error = anyhow::Error;
-    type Input = fetcher::Payload;
-    type Output = Vec<daily_close::DailyClose>;
+    type Input = fetcher::PayloadStream;
+    type Output = Parsed<daily_close::DailyClose>;
 
     async fn parse(&self, payload: Self::Input) -> Result<Self::Output, Self::Error> {
-        let index_set = if payload.source.contains("twse") {
-            daily_close::CsvIndexSet::new_twse()
-        } else if payload.source.contains("tpex") {
-            daily_close::CsvIndexSet::new_tpex()
-        } else {
-            return Err(anyhow!("Cannot identify parse index"));
-        };
+        let date = payload.date.clone();
+        let source = payload.source.clone();
+        let allowed = SETTINGS.securities.allowed("daily_close").to_vec();
+        let reader = payload.into_sync_reader();
+        tokio::task::spawn_blocking(move || {
+            DailyCloseJsonStrategy.parse_table(reader, &source, SCHEMAS.as_ref(), &allowed, &date)
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::setting::SchemaSettings;
+    use crate::engine::schema::SchemaDrift;
+
+    const ALLOWED: &[SecurityClass] = &[SecurityClass::CommonStock, SecurityClass::Etf];
+
+    #[test]
+    fn test_parse_records_follows_header() {
+        // a column inserted before the prices shifts every position after it
+        let csv = "\"113年07月23日 每日收盤行情\"\n\
+            \"證券代號\",\"證券名稱\",\"成交股數\",\"成交筆數\",\"成交金額\",\"漲停價\",\"開盤價\",\"最高價\",\"最低價\",\"收盤價\",\"漲跌(+/-)\",\"漲跌價差\",\n\
+            \"2330 \",\"台積電\",\"30,402,548\",\"64,436\",\"29,362,416,090\",\"1,058.00\",\"962.00\",\"973.00\",\"958.00\",\"964.00\",\"-\",\"9.00\",\n";
+        let date = Some("20240723".to_string());
 
-        let mut rdr = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .delimiter(b',')
-            .flexible(true)
-            .from_reader(payload.content.as_bytes());
+        let records = DailyCloseStrategy
+            .parse_records(csv.as_bytes(), "twse", None, None, ALLOWED, &date)
+            .unwrap()
+            .records;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].stock_id, "2330");
+        assert_eq!(records[0].open, 962.0);
+        assert_eq!(records[0].close, 964.0);
+        assert_eq!(records[0].diff, -9.0);
+
+        let headless = "\"2330\",\"台積電\",\"30,402,548\",\"64,436\",\"29,362,416,090\",\"962.00\",\"973.00\",\"958.00\",\"964.00\",\"-\",\"9.00\",\n";
+        assert!(DailyCloseStrategy
+            .parse_records(headless.as_bytes(), "twse", None, None, ALLOWED, &date)
+            .is_err());
+        let records = DailyCloseStrategy
+            .parse_records(
+                headless.as_bytes(),
+                "twse",
+                None,
+                Some(daily_close::CsvIndexSet::new_twse()),
+                ALLOWED,
+                &date,
+            )
+            .unwrap()
+            .records;
+        assert_eq!(records[0].close, 964.0);
+    }
+
+    #[test]
+    fn test_parse_records_reports_rejections() {
+        let csv = "\"證券代號\",\"證券名稱\",\"成交股數\",\"成交筆數\",\"成交金額\",\"開盤價\",\"最高價\",\"最低價\",\"收盤價\",\"漲跌(+/-)\",\"漲跌價差\",\n\
+            \"2330\",\"台積電\",\"30,402,548\",\"64,436\",\"29,362,416,090\",\"962.00\",\"973.00\",\"958.00\",\"964.00\",\"-\",\"9.00\",\n\
+            \"9999\",\"停牌股\",\"0\",\"0\",\"0\",\"--\",\"--\",\"--\",\"--\",\" \",\"0.00\",\n\
+            \"1101\",\"台泥\",\"9,876,543\",\"5,432\",\"N/A\",\"32.50\",\"32.70\",\"32.40\",\"32.60\",\" \",\"0.00\",\n\
+            \"030001\",\"台積電元大41購01\",\"1,000\",\"2\",\"500\",\"0.50\",\"0.50\",\"0.50\",\"0.50\",\" \",\"0.00\",\n\
+            \"2317\",\"鴻海\",\"1,000\",\n\
+            \"備註:\"\n\
+            \"1.漲跌(+/-)欄位符號說明:+/-/X表示漲/跌/不比價。\"\n";
+
+        let parsed = DailyCloseStrategy
+            .parse_records(csv.as_bytes(), "twse", None, None, ALLOWED, &None)
+            .unwrap();
+        let report = parsed.report;
+        assert_eq!(parsed.records.len(), 1);
+        // the footer and the notes below it are no rows of the table
+        assert_eq!((report.total, report.accepted), (5, 1));
+        assert_eq!(report.rejected["security class not ingested"].count, 1);
+        assert_eq!(report.rejected["missing value"].count, 1);
+        assert!(report.rejected["missing value"].samples[0].starts_with("9999,"));
+        assert_eq!(report.rejected["unparsable number"].count, 1);
+        assert_eq!(report.rejected["too few fields"].count, 1);
+    }
+
+    #[test]
+    fn test_parse_records_detects_drift() {
+        let schemas = Schemas::new(&SchemaSettings {
+            path: concat!(env!("CARGO_MANIFEST_DIR"), "/schemas").to_string(),
+            ..Default::default()
+        });
+        let source = "https://www.twse.com.tw/exchangeReport/MI_INDEX?response=csv";
+        let header = "\"證券代號\",\"證券名稱\",\"成交股數\",\"成交筆數\",\"成交金額\",\"開盤價\",\"最高價\",\"最低價\",\"收盤價\",\"漲跌(+/-)\",\"漲跌價差\",\"最後揭示買價\",\"最後揭示買量\",\"最後揭示賣價\",\"最後揭示賣量\",\"本益比\",\n";
+        let row = "\"2330\",\"台積電\",\"30,402,548\",\"64,436\",\"29,362,416,090\",\"962.00\",\"973.00\",\"958.00\",\"964.00\",\"-\",\"9.00\",\"963.00\",\"573\",\"964.00\",\"1,062\",\"25.21\",\n";
+
+        let parsed = DailyCloseStrategy
+            .parse_records(
+                format!("{}{}", header, row).as_bytes(),
+                source,
+                Some(&schemas),
+                None,
+                ALLOWED,
+                &None,
+            )
+            .unwrap();
+        assert_eq!(parsed.records.len(), 1);
+
+        // a price limit column inserted before the prices stops the parse
+        let drifted = header.replace("\"開盤價\"", "\"漲停價\",\"開盤價\"");
+        let error = DailyCloseStrategy
+            .parse_records(
+                format!("{}{}", drifted, row).as_bytes(),
+                source,
+                Some(&schemas),
+                None,
+                ALLOWED,
+                &None,
+            )
+            .unwrap_err();
+        let drift = error.downcast_ref::<SchemaDrift>().unwrap();
+        assert_eq!(drift.layout, "daily_close.twse");
+        assert!(drift
+            .changes
+            .contains(&"column \"漲停價\" added at 5".to_string()));
+    }
 
-        let records = rdr
-            .records()
-            .filter_map(|result| result.ok())
-            .filter(|record| self.is_valid_record(record, &index_set))
-            .filter_map(|record| self.parse_record(&record, &index_set, &payload.date).ok())
-            .collect();
+    #[test]
+    fn test_parse_table_against_shipped_schemas() {
+        let schemas = Schemas::new(&SchemaSettings {
+            path: concat!(env!("CARGO_MANIFEST_DIR"), "/schemas").to_string(),
+            ..Default::default()
+        });
+        let source = "https://www.twse.com.tw/rwd/zh/afterTrading/MI_INDEX?response=json";
+        let body = r#"{"stat":"OK","tables":[
+            {"title":"價格指數","fields":["指數","收盤指數"],"data":[]},
+            {"title":"每日收盤行情","fields":["證券代號","證券名稱","成交股數","成交筆數","成交金額","開盤價","最高價","最低價","收盤價","漲跌(+/-)","漲跌價差","最後揭示買價","最後揭示買量","最後揭示賣價","最後揭示賣量","本益比"],
+             "data":[["2330","台積電","30,402,548","64,436","29,362,416,090","962.00","973.00","958.00","964.00","<p style= color:green>-</p>","9.00","963.00","573","964.00","1,062","25.21"]]}
+        ]}"#;
 
-        Ok(records)
+        let parsed = DailyCloseJsonStrategy
+            .parse_table(body.as_bytes(), source, Some(&schemas), ALLOWED, &None)
+            .unwrap();
+        assert_eq!(parsed.records.len(), 1);
+        assert_eq!(parsed.records[0].close, 964.0);
+        assert_eq!(parsed.records[0].diff, -9.0);
     }
 }
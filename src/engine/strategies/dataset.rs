@@ -0,0 +1,380 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use csv::StringRecord;
+use scraper::{Html, Selector};
+use serde_json::{Map, Number, Value};
+use std::io::Read;
+use std::sync::Arc;
+
+use crate::config::setting::SETTINGS;
+use crate::engine::columns;
+use crate::engine::dataset::{Dataset, Field, FieldType, Format};
+use crate::engine::fetcher;
+use crate::engine::parser::*;
+use crate::engine::schema::{self, Fingerprint, Schemas, SCHEMAS};
+use crate::engine::security::SecurityClass;
+
+use super::rows;
+
+/// Record of a declarative dataset, keyed by field
+pub type Record = Map<String, Value>;
+
+/// Parses any dataset by following its YAML spec
+#[derive(Debug)]
+pub struct DatasetStrategy {
+    dataset: Arc<Dataset>,
+}
+
+impl Conversion for DatasetStrategy {}
+
+impl DatasetStrategy {
+    pub fn new(dataset: Arc<Dataset>) -> Self {
+        Self { dataset }
+    }
+
+    /// Value of `field` read from its cell, or why the row is rejected
+    fn value(
+        &self,
+        field: &Field,
+        cell: Option<&str>,
+        allowed: &[SecurityClass],
+    ) -> Result<Value, &'static str> {
+        let numeric = matches!(field.kind, FieldType::Integer | FieldType::Float);
+        // a cell of dashes only holds no number
+        let cell = cell
+            .map(str::trim)
+            .filter(|cell| !cell.is_empty() && (!numeric || cell.chars().any(|c| c != '-')));
+        let Some(cell) = cell else {
+            return if field.required {
+                Err("missing value")
+            } else {
+                Ok(Value::Null)
+            };
+        };
+        if field.pattern.as_ref().is_some_and(|p| !p.is_match(cell)) {
+            return Err("pattern mismatch");
+        }
+
+        let number = match field.kind {
+            FieldType::String => return Ok(Value::String(cell.to_string())),
+            FieldType::Security => {
+                return match SecurityClass::of(cell) {
+                    None => Err("not a security code"),
+                    Some(class) if !allowed.contains(&class) => Err("security class not ingested"),
+                    Some(_) => Ok(Value::String(cell.to_string())),
+                }
+            }
+            FieldType::Integer => self
+                .parse_with_comma::<i64>(cell)
+                .map(Number::from)
+                .map_err(|_| "unparsable number")?,
+            FieldType::Float => self
+                .parse_with_comma::<f64>(cell.trim_end_matches('%'))
+                .ok()
+                .and_then(Number::from_f64)
+                .ok_or("unparsable number")?,
+        };
+        let n = number.as_f64().unwrap_or_default();
+        if field.min.is_some_and(|min| n < min) || field.max.is_some_and(|max| n > max) {
+            return Err("out of range");
+        }
+        Ok(Value::Number(number))
+    }
+
+    /// Record of a row, `cell` reading the cell of each field
+    fn record<'a>(
+        &self,
+        cell: impl Fn(usize) -> Option<&'a str>,
+        allowed: &[SecurityClass],
+        date: &Option<String>,
+    ) -> Result<Record, &'static str> {
+        let mut record = Record::new();
+        for (index, field) in self.dataset.fields.iter().enumerate() {
+            let value = self.value(field, cell(index), allowed)?;
+            record.insert(field.key.clone(), value);
+        }
+        if let Some(date) = date {
+            record.insert("date".to_string(), Value::String(date.clone()));
+        }
+        Ok(record)
+    }
+
+    /// Positions of the fields when `record` is the header row, i.e. names the
+    /// first field
+    fn locate(&self, record: &StringRecord) -> Option<Vec<Option<usize>>> {
+        let headers: Vec<String> = record.iter().map(columns::normalize).collect();
+        let positions: Vec<Option<usize>> = self
+            .dataset
+            .fields
+            .iter()
+            .map(|field| {
+                field.columns.iter().find_map(|name| {
+                    let name = columns::normalize(name);
+                    headers.iter().position(|header| *header == name)
+                })
+            })
+            .collect();
+        positions.first().copied().flatten().map(|_| positions)
+    }
+
+    /// Parse CSV rows, mapping fields from the header row among them
+    fn parse_rows(
+        &self,
+        rows: impl Iterator<Item = csv::Result<StringRecord>>,
+        source: &str,
+        schemas: Option<&Schemas>,
+        allowed: &[SecurityClass],
+        date: &Option<String>,
+    ) -> Result<Parsed<Record>> {
+        rows::parse_table(
+            rows,
+            &self.dataset.name,
+            source,
+            schemas,
+            None,
+            |record| {
+                self.locate(record).map(|positions| {
+                    let lacks_required = self
+                        .dataset
+                        .fields
+                        .iter()
+                        .zip(positions.iter())
+                        .any(|(field, position)| field.required && position.is_none());
+                    if lacks_required {
+                        return Err(anyhow!("Header row of {} lacks required columns", source));
+                    }
+                    Ok(positions)
+                })
+            },
+            |row, positions| {
+                let width = positions.iter().flatten().max().map_or(0, |max| max + 1);
+                if row.len() < width {
+                    return Err("too few fields");
+                }
+                self.record(
+                    |index| positions[index].and_then(|p| row.get(p)),
+                    allowed,
+                    date,
+                )
+            },
+        )
+    }
+
+    /// Parse the rows of an HTML page, reading each field by its selector
+    fn parse_document(
+        &self,
+        document: &Html,
+        source: &str,
+        schemas: Option<&Schemas>,
+        allowed: &[SecurityClass],
+        date: &Option<String>,
+    ) -> Result<Parsed<Record>> {
+        let rows = self.dataset.rows.as_deref().unwrap_or_default();
+        if let Some(schemas) = schemas {
+            let layout = schema::layout(&self.dataset.name, source);
+            schemas.check(&layout, source, &Fingerprint::html(document, &[rows])?)?;
+        }
+        let parse = |selector: &str| {
+            Selector::parse(selector)
+                .map_err(|e| anyhow!("Failed to create selector {}: {}", selector, e))
+        };
+        let row_selector = parse(rows)?;
+        let selectors = self
+            .dataset
+            .fields
+            .iter()
+            .map(|field| parse(field.selector.as_deref().unwrap_or_default()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut records = Vec::new();
+        let mut report = ParseReport::default();
+        for row in document.select(&row_selector) {
+            let cells: Vec<Option<String>> = selectors
+                .iter()
+                .map(|selector| {
+                    row.select(selector)
+                        .next()
+                        .map(|cell| cell.text().collect::<String>())
+                })
+                .collect();
+            let cell = |index: usize| cells[index].as_deref();
+            match self.record(cell, allowed, date) {
+                Ok(record) => {
+                    report.accept();
+                    records.push(record);
+                }
+                Err(reason) => {
+                    let sample = cells.iter().map(|cell| cell.as_deref().unwrap_or_default());
+                    report.reject(reason, &sample.collect::<StringRecord>());
+                }
+            }
+        }
+        Ok(Parsed { records, report })
+    }
+
+    fn parse_body(
+        &self,
+        mut reader: impl Read,
+        source: &str,
+        allowed: &[SecurityClass],
+        date: &Option<String>,
+    ) -> Result<Parsed<Record>> {
+        match self.dataset.format {
+            Format::Csv => self.parse_rows(
+                rows::records(reader),
+                source,
+                SCHEMAS.as_ref(),
+                allowed,
+                date,
+            ),
+            Format::Html => {
+                let mut content = String::new();
+                reader.read_to_string(&mut content)?;
+                let document = Html::parse_document(&content);
+                self.parse_document(&document, source, SCHEMAS.as_ref(), allowed, date)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ParseStrategy for DatasetStrategy {
+    type Error = anyhow::Error;
+    type Input = fetcher::PayloadStream;
+    type Output = Parsed<Record>;
+
+    async fn parse(&self, payload: Self::Input) -> Result<Self::Output, Self::Error> {
+        // the bridge needs the runtime, reading happens off it
+        let date = payload.date.clone();
+        let source = payload.source.clone();
+        let allowed = SETTINGS.securities.allowed(&self.dataset.name).to_vec();
+        let strategy = DatasetStrategy::new(self.dataset.clone());
+        let reader = payload.into_sync_reader();
+        tokio::task::spawn_blocking(move || strategy.parse_body(reader, &source, &allowed, &date))
+            .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::setting::SchemaSettings;
+    use std::path::Path;
+
+    const ALLOWED: &[SecurityClass] = &[SecurityClass::CommonStock, SecurityClass::Etf];
+
+    fn dataset(spec: &str) -> DatasetStrategy {
+        DatasetStrategy::new(Arc::new(serde_yaml::from_str(spec).unwrap()))
+    }
+
+    #[test]
+    fn test_parse_csv_by_spec() {
+        let strategy = dataset(
+            r#"
+name: foreign_holding
+topic: foreignholding-v1
+sources: []
+fields:
+  - key: stockId
+    columns: [證券代號]
+    type: security
+  - key: holding
+    columns: [全體外資及陸資持有股數]
+    type: integer
+  - key: ratio
+    columns: [全體外資及陸資持股比率]
+    type: float
+    max: 100
+"#,
+        );
+        let csv = "\"113年07月23日 外資及陸資投資持股統計\"\n\
+            \"證券代號\",\"證券名稱\",\"全體外資及陸資持有股數\",\"全體外資及陸資持股比率\",\n\
+            \"2330\",\"台積電\",\"18,648,151,386\",\"71.91\",\n\
+            \"030001\",\"權證\",\"0\",\"0.00\",\n\
+            \"1101\",\"台泥\",\"--\",\"12.00\",\n\
+            \"2002\",\"中鋼\",\"100\",\"120.00\",\n\
+            \"說明:\"\n";
+        let date = Some("20240723".to_string());
+
+        let parsed = strategy
+            .parse_rows(rows::records(csv.as_bytes()), "twse", None, ALLOWED, &date)
+            .unwrap();
+        assert_eq!(parsed.records.len(), 1);
+        assert_eq!(
+            Value::Object(parsed.records[0].clone()).to_string(),
+            r#"{"date":"20240723","holding":18648151386,"ratio":71.91,"stockId":"2330"}"#
+        );
+        let rejected = &parsed.report.rejected;
+        assert_eq!(rejected["security class not ingested"].count, 1);
+        assert_eq!(rejected["missing value"].count, 1);
+        assert_eq!(rejected["out of range"].count, 1);
+        assert!(!rejected.contains_key("too few fields"));
+    }
+
+    #[test]
+    fn test_parse_html_by_spec() {
+        let strategy = dataset(
+            r#"
+name: brokers
+topic: brokers-v1
+sources: []
+format: html
+rows: "table.hasBorder tr"
+fields:
+  - key: broker
+    selector: "td.t4t1 a"
+  - key: buy
+    selector: "td.t3n1:nth-child(2)"
+    type: integer
+"#,
+        );
+        let document = Html::parse_document(
+            r#"<table class="hasBorder">
+            <tr><td class="t4t1">券商</td><td class="t4t1">買進</td></tr>
+            <tr><td class="t4t1"><a href="?BHID=5380">第一金-自由</a></td><td class="t3n1">1,034</td></tr>
+            </table>"#,
+        );
+
+        let parsed = strategy
+            .parse_document(&document, "fbs", None, ALLOWED, &None)
+            .unwrap();
+        assert_eq!(parsed.records.len(), 1);
+        assert_eq!(parsed.records[0]["broker"], "第一金-自由");
+        assert_eq!(parsed.records[0]["buy"], 1034);
+        assert_eq!(parsed.report.rejected["missing value"].count, 1);
+    }
+
+    #[test]
+    fn test_shipped_specs_match_shipped_schemas() {
+        let root = concat!(env!("CARGO_MANIFEST_DIR"), "/datasets");
+        let schemas = Schemas::new(&SchemaSettings {
+            path: concat!(env!("CARGO_MANIFEST_DIR"), "/schemas").to_string(),
+            ..Default::default()
+        });
+        for entry in std::fs::read_dir(root).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_stem().unwrap().to_str().unwrap();
+            let dataset = Dataset::find(Path::new(root), name).unwrap().unwrap();
+            let strategy = DatasetStrategy::new(Arc::new(dataset.clone()));
+            for source in dataset.sources.iter() {
+                let layout = schema::layout(&dataset.name, &source.url);
+                let expected = schemas
+                    .expected(&layout)
+                    .unwrap()
+                    .unwrap_or_else(|| panic!("{} ships no fingerprint {}", name, layout));
+                // the expected header row maps every field and passes the check
+                let header = StringRecord::from(expected.headers);
+                let parsed = strategy
+                    .parse_rows(
+                        std::iter::once(Ok(header)),
+                        &source.url,
+                        Some(&schemas),
+                        ALLOWED,
+                        &None,
+                    )
+                    .unwrap();
+                assert_eq!(parsed.report.total, 0);
+            }
+        }
+    }
+}
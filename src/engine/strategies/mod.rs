@@ -1,3 +1,6 @@
 pub mod concentration;
 pub mod daily_close;
+pub mod dataset;
+pub mod rows;
+pub mod rwd;
 pub mod three_primary;
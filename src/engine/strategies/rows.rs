@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use std::io::Read;
+
+use crate::config::setting::SETTINGS;
+use crate::engine::columns::IndexSet;
+use crate::engine::parser::{ParseReport, Parsed};
+use crate::engine::schema::{self, Fingerprint, Schemas};
+use crate::engine::security::SecurityClass;
+
+/// Positional fallback for the export at `source`, when enabled
+pub fn fallback<I: IndexSet>(source: &str) -> Result<Option<I>> {
+    if !SETTINGS.parsing.positional_fallback {
+        return Ok(None);
+    }
+    I::positional(source)
+        .map(Some)
+        .ok_or_else(|| anyhow!("Cannot identify parse index"))
+}
+
+/// Records of a CSV export, read as they arrive
+pub fn records(reader: impl Read) -> impl Iterator<Item = csv::Result<StringRecord>> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .delimiter(b',')
+        .flexible(true)
+        .from_reader(reader)
+        .into_records()
+}
+
+/// Parse rows of `dataset` mapping columns from the header row among them,
+/// `parse` turning each valid row into a record. Rejected rows are counted by
+/// reason in the report. Rows that cannot be read or hold no header row fail
+/// unless a positional `fallback` is given, and so does a header row drifting
+/// from the expected layout.
+pub fn parse_rows<I: IndexSet, T>(
+    rows: impl Iterator<Item = csv::Result<StringRecord>>,
+    dataset: &str,
+    source: &str,
+    schemas: Option<&Schemas>,
+    fallback: Option<I>,
+    allowed: &[SecurityClass],
+    parse: impl Fn(&StringRecord, &I) -> Result<T>,
+) -> Result<Parsed<T>> {
+    parse_table(
+        rows,
+        dataset,
+        source,
+        schemas,
+        fallback,
+        |record| {
+            I::is_header(record).then(|| {
+                I::from_header(record)
+                    .ok_or_else(|| anyhow!("Header row of {} lacks required columns", source))
+            })
+        },
+        |record, index_set| match rejection(record, index_set, allowed) {
+            Some(reason) => Err(reason),
+            None => parse(record, index_set).map_err(|_| "unparsable number"),
+        },
+    )
+}
+
+/// `parse_rows` for any mapping of columns: `header` maps the columns of the
+/// row it takes for the header row, failing when required ones are missing,
+/// and `parse` turns each row below it into a record or the reason it is
+/// rejected
+pub fn parse_table<I, T>(
+    rows: impl Iterator<Item = csv::Result<StringRecord>>,
+    dataset: &str,
+    source: &str,
+    schemas: Option<&Schemas>,
+    fallback: Option<I>,
+    header: impl Fn(&StringRecord) -> Option<Result<I>>,
+    parse: impl Fn(&StringRecord, &I) -> Result<T, &'static str>,
+) -> Result<Parsed<T>> {
+    let mut index = None;
+    let mut records = Vec::new();
+    let mut report = ParseReport::default();
+    for result in rows {
+        let record = match result {
+            Ok(record) => record,
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            Err(e) => {
+                report.reject("malformed CSV", &StringRecord::from(vec![e.to_string()]));
+                continue;
+            }
+        };
+        if index.is_none() {
+            if let Some(mapped) = header(&record) {
+                if let Some(schemas) = schemas {
+                    let layout = schema::layout(dataset, source);
+                    schemas.check(&layout, source, &Fingerprint::csv(&record))?;
+                }
+                index = Some(mapped?);
+                continue;
+            }
+        }
+
+        if is_footer(&record) {
+            // notes run from the footer down to the end of the table
+            if index.is_some() {
+                break;
+            }
+            continue;
+        }
+        let Some(index) = index.as_ref().or(fallback.as_ref()) else {
+            continue;
+        };
+        match parse(&record, index) {
+            Ok(parsed) => {
+                report.accept();
+                records.push(parsed);
+            }
+            Err(reason) => report.reject(reason, &record),
+        }
+    }
+
+    if index.is_none() {
+        if fallback.is_none() {
+            return Err(anyhow!("No header row found in {}", source));
+        }
+        eprintln!(
+            "No header row found in {}, parsed by column position",
+            source
+        );
+    }
+    Ok(Parsed { records, report })
+}
+
+/// Whether `record` opens the notes below a table, e.g. `備註:` on TWSE or
+/// `共2筆` on TPEx
+pub fn is_footer(record: &StringRecord) -> bool {
+    let first = record.get(0).unwrap_or_default().trim();
+    first.starts_with("備註")
+        || first.starts_with("說明")
+        || (first.starts_with('共') && first.ends_with('筆'))
+}
+
+/// Why a record cannot be parsed, `None` when it can
+fn rejection<I: IndexSet>(
+    record: &StringRecord,
+    index_set: &I,
+    allowed: &[SecurityClass],
+) -> Option<&'static str> {
+    if record.len() < index_set.width() {
+        return Some("too few fields");
+    }
+    match SecurityClass::of(&record[index_set.stock_id()]) {
+        None => return Some("not a security code"),
+        Some(class) if !allowed.contains(&class) => return Some("security class not ingested"),
+        Some(_) => {}
+    }
+
+    if !index_set
+        .values()
+        .into_iter()
+        .all(|index| valid(&record[index]))
+    {
+        return Some("missing value");
+    }
+    None
+}
+
+// halted and untraded securities show dashes instead of figures
+fn valid(s: &str) -> bool {
+    !s.is_empty() && s != "---" && s != "--"
+}
@@ -0,0 +1,89 @@
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::Read;
+
+/// Body of a TWSE `rwd` JSON endpoint. Single table reports such as T86 hold
+/// `fields` and `data` at the top level, MI_INDEX lists several `tables`.
+#[derive(Debug, Deserialize)]
+struct Response {
+    stat: String,
+    #[serde(default)]
+    tables: Vec<Table>,
+    #[serde(flatten)]
+    table: Table,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Table {
+    #[serde(default)]
+    fields: Vec<String>,
+    #[serde(default)]
+    data: Vec<Vec<Value>>,
+}
+
+/// Whether `source` asks TWSE for the JSON rendition of a report
+pub fn is_json(source: &str) -> bool {
+    source.contains("response=json")
+}
+
+/// Read the first table whose fields pass `is_header`, as the header record
+/// followed by its rows, the same shape as a CSV export
+pub fn table(
+    reader: impl Read,
+    source: &str,
+    is_header: impl Fn(&StringRecord) -> bool,
+) -> Result<Vec<StringRecord>> {
+    let response: Response = serde_json::from_reader(reader)?;
+    // holidays and throttling answer with a message instead of OK
+    if response.stat != "OK" {
+        return Err(anyhow!("{} answered: {}", source, response.stat));
+    }
+
+    let table = std::iter::once(response.table)
+        .chain(response.tables)
+        .find(|table| {
+            !table.fields.is_empty() && is_header(&StringRecord::from(table.fields.clone()))
+        })
+        .ok_or_else(|| anyhow!("No table of {} holds security codes", source))?;
+    Ok(std::iter::once(StringRecord::from(table.fields))
+        .chain(
+            table
+                .data
+                .into_iter()
+                .map(|row| row.into_iter().map(cell).collect::<StringRecord>()),
+        )
+        .collect())
+}
+
+fn cell(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_picks_matching_fields() {
+        let body = r#"{"stat":"OK","date":"20240723","tables":[
+            {"title":"價格指數","fields":["指數","收盤指數"],"data":[["發行量加權股價指數","23,000.00"]]},
+            {"title":"每日收盤行情","fields":["證券代號","證券名稱"],"data":[["2330","台積電"],["0050",null]]}
+        ]}"#;
+        let rows = table(body.as_bytes(), "twse", |record| {
+            record.get(0) == Some("證券代號")
+        })
+        .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1], StringRecord::from(vec!["2330", "台積電"]));
+        assert_eq!(rows[2], StringRecord::from(vec!["0050", ""]));
+
+        let holiday = r#"{"stat":"很抱歉，沒有符合條件的資料!"}"#;
+        assert!(table(holiday.as_bytes(), "twse", |_| true).is_err());
+    }
+}
@@ -1,10 +1,17 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_trait::async_trait;
 use csv::StringRecord;
+use std::io::Read;
 
+use crate::config::setting::SETTINGS;
+use crate::engine::columns::IndexSet;
 use crate::engine::fetcher;
 use crate::engine::models::*;
 use crate::engine::parser::*;
+use crate::engine::schema::{Schemas, SCHEMAS};
+use crate::engine::security::SecurityClass;
+
+use super::{rows, rwd};
 
 #[derive(Debug)]
 pub struct ThreePrimaryStrategy;
@@ -12,23 +19,6 @@ pub struct ThreePrimaryStrategy;
 impl Conversion for ThreePrimaryStrategy {}
 
 impl ThreePrimaryStrategy {
-    fn is_valid_record(
-        &self,
-        record: &StringRecord,
-        index_set: &three_primary::CsvIndexSet,
-    ) -> bool {
-        record.len() >= 19
-            && self.is_integer(&record[index_set.stock_id])
-            && [
-                index_set.foreign_trade_shares,
-                index_set.trust_trade_shares,
-                index_set.dealer_trade_shares,
-                index_set.hedging_trade_shares,
-            ]
-            .iter()
-            .all(|&index| self.valid(&record[index]))
-    }
-
     fn parse_record(
         &self,
         record: &StringRecord,
@@ -36,7 +26,7 @@ impl ThreePrimaryStrategy {
         date: &Option<String>,
     ) -> Result<three_primary::ThreePrimary> {
         Ok(three_primary::ThreePrimary {
-            stock_id: record[index_set.stock_id].to_string(),
+            stock_id: record[index_set.stock_id].trim().to_string(),
             exchange_date: date.clone().unwrap_or_default(),
             foreign_trade_shares: self
                 .parse_with_comma::<i64>(&record[index_set.foreign_trade_shares])?,
@@ -49,43 +39,129 @@ impl ThreePrimaryStrategy {
         })
     }
 
-    fn is_integer(&self, s: &str) -> bool {
-        s.parse::<i32>().is_ok() && s.len() == 4
+    /// Parse the records of a CSV export as it is read
+    fn parse_records(
+        &self,
+        reader: impl Read,
+        source: &str,
+        schemas: Option<&Schemas>,
+        fallback: Option<three_primary::CsvIndexSet>,
+        allowed: &[SecurityClass],
+        date: &Option<String>,
+    ) -> Result<Parsed<three_primary::ThreePrimary>> {
+        rows::parse_rows(
+            rows::records(reader),
+            "three_primary",
+            source,
+            schemas,
+            fallback,
+            allowed,
+            |record, index_set| self.parse_record(record, index_set, date),
+        )
     }
+}
+
+#[async_trait]
+impl ParseStrategy for ThreePrimaryStrategy {
+    type Error = anyhow::Error;
+    type Input = fetcher::PayloadStream;
+    type Output = Parsed<three_primary::ThreePrimary>;
+
+    async fn parse(&self, payload: Self::Input) -> Result<Self::Output, Self::Error> {
+        let fallback = rows::fallback::<three_primary::CsvIndexSet>(&payload.source)?;
 
-    fn valid(&self, s: &str) -> bool {
-        !s.is_empty() && s != "---"
+        // the bridge needs the runtime, reading happens off it
+        let date = payload.date.clone();
+        let source = payload.source.clone();
+        let allowed = SETTINGS.securities.allowed("three_primary").to_vec();
+        let reader = payload.into_sync_reader();
+        tokio::task::spawn_blocking(move || {
+            ThreePrimaryStrategy.parse_records(
+                reader,
+                &source,
+                SCHEMAS.as_ref(),
+                fallback,
+                &allowed,
+                &date,
+            )
+        })
+        .await?
+    }
+}
+
+/// `ThreePrimaryStrategy` over the JSON rendition of the TWSE T86 report
+#[derive(Debug)]
+pub struct ThreePrimaryJsonStrategy;
+
+impl Conversion for ThreePrimaryJsonStrategy {}
+
+impl ThreePrimaryJsonStrategy {
+    /// Parse the rows of the table holding security codes
+    fn parse_table(
+        &self,
+        reader: impl Read,
+        source: &str,
+        schemas: Option<&Schemas>,
+        allowed: &[SecurityClass],
+        date: &Option<String>,
+    ) -> Result<Parsed<three_primary::ThreePrimary>> {
+        let table = rwd::table(reader, source, three_primary::CsvIndexSet::is_header)?;
+        rows::parse_rows(
+            table.into_iter().map(Ok),
+            "three_primary",
+            source,
+            schemas,
+            None,
+            allowed,
+            |record, index_set| ThreePrimaryStrategy.parse_record(record, index_set, date),
+        )
     }
 }
 
 #[async_trait]
-impl ParseStrategy for ThreePrimaryStrategy {
+impl ParseStrategy for ThreePrimaryJsonStrategy {
     type Error = anyhow::Error;
-    type Input = fetcher::Payload;
-    type Output = Vec<three_primary::ThreePrimary>;
+    type Input = fetcher::PayloadStream;
+    type Output = Parsed<three_primary::ThreePrimary>;
 
     async fn parse(&self, payload: Self::Input) -> Result<Self::Output, Self::Error> {
-        let index_set = if payload.source.contains("twse") {
-            three_primary::CsvIndexSet::new_twse()
-        } else if payload.source.contains("tpex") {
-            three_primary::CsvIndexSet::new_tpex()
-        } else {
-            return Err(anyhow!("Cannot identify parse index"));
-        };
-
-        let mut rdr = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .delimiter(b',')
-            .flexible(true)
-            .from_reader(payload.content.as_bytes());
-
-        let records = rdr
-            .records()
-            .filter_map(|result| result.ok())
-            .filter(|record| self.is_valid_record(record, &index_set))
-            .filter_map(|record| self.parse_record(&record, &index_set, &payload.date).ok())
-            .collect();
-
-        Ok(records)
+        let date = payload.date.clone();
+        let source = payload.source.clone();
+        let allowed = SETTINGS.securities.allowed("three_primary").to_vec();
+        let reader = payload.into_sync_reader();
+        tokio::task::spawn_blocking(move || {
+            ThreePrimaryJsonStrategy.parse_table(reader, &source, SCHEMAS.as_ref(), &allowed, &date)
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::setting::SchemaSettings;
+
+    #[test]
+    fn test_parse_table_against_shipped_schemas() {
+        let schemas = Schemas::new(&SchemaSettings {
+            path: concat!(env!("CARGO_MANIFEST_DIR"), "/schemas").to_string(),
+            ..Default::default()
+        });
+        let source = "https://www.twse.com.tw/rwd/zh/fund/T86?response=json";
+        let body = r#"{"stat":"OK","fields":["證券代號","證券名稱","外陸資買進股數(不含外資自營商)","外陸資賣出股數(不含外資自營商)","外陸資買賣超股數(不含外資自營商)","外資自營商買進股數","外資自營商賣出股數","外資自營商買賣超股數","投信買進股數","投信賣出股數","投信買賣超股數","自營商買賣超股數","自營商買進股數(自行買賣)","自營商賣出股數(自行買賣)","自營商買賣超股數(自行買賣)","自營商買進股數(避險)","自營商賣出股數(避險)","自營商買賣超股數(避險)","三大法人買賣超股數"],
+            "data":[["2330","台積電","20,000,000","15,000,000","5,000,000","0","0","0","300,000","100,000","200,000","-50,000","10,000","20,000","-10,000","30,000","70,000","-40,000","5,150,000"]]}"#;
+
+        let parsed = ThreePrimaryJsonStrategy
+            .parse_table(
+                body.as_bytes(),
+                source,
+                Some(&schemas),
+                &[SecurityClass::CommonStock],
+                &None,
+            )
+            .unwrap();
+        assert_eq!(parsed.records.len(), 1);
+        assert_eq!(parsed.records[0].foreign_trade_shares, 5_000_000);
+        assert_eq!(parsed.records[0].hedging_trade_shares, -40_000);
     }
 }
@@ -6,9 +6,11 @@ mod repository;
 use chrono::{DateTime, Local, NaiveDate, TimeZone};
 use clap::Parser;
 use config::setting::SETTINGS;
+use engine::fetcher::{archive, fetch_all_content};
 use futures::future::abortable;
 use repository::adapter::Adapter;
 use sqlx::postgres::PgPoolOptions;
+use std::path::{Path, PathBuf};
 use tokio::signal;
 
 #[derive(Parser, Debug)]
@@ -21,6 +23,11 @@ struct Args {
     /// Date in the format "YYYYMMDD"
     #[arg(short, long)]
     date: Option<String>,
+
+    /// Replay archived payloads from the directory, or any fetchable source
+    /// such as `-`, `zip://<file>` or `dir://<path>`, instead of fetching
+    #[arg(long)]
+    replay: Option<String>,
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 4)]
@@ -39,8 +46,71 @@ async fn main() {
         None => Local::now(),
     };
 
+    // Any other target names a declarative dataset, unknown ones fail the run
+    let dataset = match args.target.as_str() {
+        "daily_close" | "three_primary" | "concentration" => None,
+        target => match process::dataset::find(target) {
+            Some(dataset) => Some(dataset),
+            None => std::process::exit(1),
+        },
+    };
+
+    // Accept both the archive root and a single <target>/<date> directory
+    let replay_source = args.replay.map(|source| {
+        if source == "-" || source.contains("://") {
+            return source;
+        }
+        let root = PathBuf::from(source);
+        let dir = root
+            .join(&args.target)
+            .join(date.format("%Y%m%d").to_string());
+        if dir.is_dir() {
+            dir.display().to_string()
+        } else if root.join(&args.target).is_dir() {
+            eprintln!("No archived payloads in {}", dir.display());
+            std::process::exit(1);
+        } else {
+            root.display().to_string()
+        }
+    });
+
     // Create a task handle for the main processing logic
     let (crawling_task, abort_handle) = abortable(async move {
+        if let Some(source) = replay_source {
+            println!("Replaying payloads from {}", source);
+            let payloads = if source == "-" || source.contains("://") {
+                fetch_all_content(&source)
+                    .await
+                    .map_err(anyhow::Error::from)
+            } else {
+                archive::load_dir(Path::new(&source)).await
+            };
+            // replaying nothing would pass for a run that published nothing
+            let payloads = match payloads {
+                Ok(payloads) if payloads.is_empty() => {
+                    eprintln!("No archived payloads in {}", source);
+                    std::process::exit(1);
+                }
+                Ok(payloads) => payloads,
+                Err(e) => {
+                    eprintln!("Failed to load {}: {}", source, e);
+                    std::process::exit(1);
+                }
+            };
+
+            match args.target.as_str() {
+                "daily_close" => process::daily_close::replay(date, payloads).await,
+                "three_primary" => process::three_primary::replay(date, payloads).await,
+                "concentration" => process::concentration::replay(date, payloads).await,
+                _ => {
+                    if let Some(dataset) = dataset {
+                        process::dataset::replay(date, dataset, payloads).await
+                    }
+                }
+            }
+            return;
+        }
+
         match args.target.as_str() {
             "daily_close" => process::daily_close::execute(date).await,
             "three_primary" => process::three_primary::execute(date).await,
@@ -56,9 +126,13 @@ async fn main() {
                 let pg_adapter = Adapter::new(pool);
                 let ids = pg_adapter.get_stock_ids().await.ok().unwrap();
 
-                process::concentration::execute(ids).await;
+                process::concentration::execute(date, ids).await;
+            }
+            _ => {
+                if let Some(dataset) = dataset {
+                    process::dataset::execute(date, dataset).await
+                }
             }
-            target => eprintln!("Unknown target: {}", target),
         }
     });
 
@@ -76,4 +150,7 @@ async fn main() {
     }
 
     println!("Shutdown complete");
+    if process::failed() {
+        std::process::exit(1);
+    }
 }
@@ -1,20 +1,19 @@
 use super::kafka::Producer;
 use crate::config::setting::SETTINGS;
-use crate::engine::fetcher::{fetch_content, Payload};
+use crate::engine::fetcher::{fetch_and_archive, FetchError, Payload};
 use crate::engine::models::concentration::Concentration;
 use crate::engine::parser::Parser;
-use crate::engine::strategies::concentration::ConcentrationStrategy;
+use crate::engine::strategies::concentration::{BrokerStrategy, ConcentrationStrategy};
 
-use chrono::{Datelike, Local};
+use chrono::{DateTime, Datelike, Local};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{mpsc, Semaphore};
 
 static CONCENTRATION_PAGES: usize = 5;
 
-pub async fn execute(stocks: Vec<String>) {
-    let today = Local::now();
-    let formatted_date = format!("{}{:02}{:02}", today.year(), today.month(), today.day());
+pub async fn execute(date: DateTime<Local>, stocks: Vec<String>) {
+    let formatted_date = format!("{}{:02}{:02}", date.year(), date.month(), date.day());
 
     // If formatted date in array, skip
     let skipped_dates = vec![
@@ -32,14 +31,28 @@ pub async fn execute(stocks: Vec<String>) {
 
     // retrieve all handles and ensure process not termiated before tasks completed
     let url_gen_handle = tokio::spawn(generate_urls(url_tx, stocks.clone()));
-    let fetch_aggregate_handle = tokio::spawn(fetch_urls(url_rx, capacity));
+    let fetch_aggregate_handle = tokio::spawn(fetch_urls(formatted_date, url_rx, capacity));
 
     // Await on both handles to ensure completion
     let _results = tokio::try_join!(url_gen_handle, fetch_aggregate_handle);
 }
 
+/// Run the aggregation on previously archived payloads instead of live HTTP
+pub async fn replay(date: DateTime<Local>, payloads: Vec<Payload>) {
+    let formatted_date = format!("{}{:02}{:02}", date.year(), date.month(), date.day());
+    let (content_tx, content_rx) = mpsc::channel(payloads.len().max(1));
+    for payload in payloads {
+        content_tx
+            .send(payload)
+            .await
+            .expect("Failed to send payload");
+    }
+    drop(content_tx);
+
+    aggregate(formatted_date, content_rx).await;
+}
+
 async fn generate_urls(url_tx: mpsc::Sender<String>, stocks: Vec<String>) {
-    let mut sum = 0;
     for stock in stocks.iter() {
         for i in 1..=CONCENTRATION_PAGES {
             // skip the 40 days calculation
@@ -50,36 +63,41 @@ async fn generate_urls(url_tx: mpsc::Sender<String>, stocks: Vec<String>) {
             );
             url_tx.send(url).await.expect("Failed to send URL");
         }
-        sum += CONCENTRATION_PAGES;
-        if sum % 25 == 0 {
-            // Sleep for a while
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-        }
     }
 
     drop(url_tx);
 }
 
-async fn fetch_urls(mut url_rx: mpsc::Receiver<String>, capacity: usize) {
+async fn fetch_urls(formatted_date: String, mut url_rx: mpsc::Receiver<String>, capacity: usize) {
     let semaphore = Arc::new(Semaphore::new(50));
     let (content_tx, content_rx) = mpsc::channel(capacity);
+    let archive_date = formatted_date.clone();
 
     let fetch_handle = tokio::spawn(async move {
         while let Some(url) = url_rx.recv().await {
             let sem_clone = Arc::clone(&semaphore);
             let content_tx_clone = content_tx.clone();
+            let archive_date = archive_date.clone();
             tokio::spawn(async move {
                 let _permit = sem_clone
                     .acquire()
                     .await
                     .expect("Failed to acquire semaphore permit");
 
-                match fetch_content(url.clone()).await {
+                match fetch_and_archive(url.clone(), "concentration", &archive_date).await {
                     Ok(payload) => {
                         if let Err(e) = content_tx_clone.send(payload).await {
                             eprintln!("Failed to send content: {}", e);
                         }
                     }
+                    // the stock has no page, e.g. newly listed or delisted
+                    Err(FetchError::NotFound(_)) => {
+                        println!("No concentration page for URL {}, skipped", url);
+                    }
+                    // the breaker already logged the outage, only the page is named
+                    Err(FetchError::CircuitOpen(_)) => {
+                        println!("Host unavailable for URL {}, skipped", url);
+                    }
                     Err(e) => {
                         eprintln!("Failed to fetch content for URL {}: {}", url, e);
                         // continue to the next URL without sending anything to content_tx
@@ -90,15 +108,13 @@ async fn fetch_urls(mut url_rx: mpsc::Receiver<String>, capacity: usize) {
         }
     });
 
-    let aggregate_handle = tokio::spawn(aggregate(content_rx));
+    let aggregate_handle = tokio::spawn(aggregate(formatted_date, content_rx));
 
     // Await on both handles to ensure completion
     let _results = tokio::try_join!(fetch_handle, aggregate_handle);
 }
 
-async fn aggregate(mut content_rx: mpsc::Receiver<Payload>) {
-    let today = Local::now();
-    let formatted_date = format!("{}{:02}{:02}", today.year(), today.month(), today.day());
+async fn aggregate(formatted_date: String, mut content_rx: mpsc::Receiver<Payload>) {
     let mut stock_map: HashMap<String, Concentration> = HashMap::new();
     // Create a new producer using match to handle the Result
     let kproducer = match Producer::new(&SETTINGS.kafka.connection_string()) {
@@ -109,46 +125,123 @@ async fn aggregate(mut content_rx: mpsc::Receiver<Payload>) {
         }
     };
 
-    while let Some(payload) = content_rx.recv().await {
+    while let Some(mut payload) = content_rx.recv().await {
         let url = payload.source.clone();
-        let parser = Parser::new(ConcentrationStrategy);
-        let res = parser.parse(payload).await;
-
-        if let Ok(res_value) = res {
-            let model = stock_map
-                .entry(res_value.0.clone())
-                .or_insert_with(|| Concentration {
-                    stock_id: res_value.0,
-                    exchange_date: formatted_date.clone(),
-                    concentration: vec![0; 5],
-                    sum_buy_shares: 0,
-                    sum_sell_shares: 0,
-                    avg_buy_price: 0.0,
-                    avg_sell_price: 0.0,
-                    current: 0,
-                });
-            model.concentration[res_value.1] = res_value.2; // Set diff num based on index
-
-            if res_value.1 == 0 {
-                model.sum_buy_shares = res_value.3;
-                model.sum_sell_shares = res_value.4;
-                model.avg_buy_price = res_value.5;
-                model.avg_sell_price = res_value.6;
+        payload.date = Some(formatted_date.clone());
+        let payload = Arc::new(payload);
+
+        match Parser::new(ConcentrationStrategy)
+            .parse(payload.clone())
+            .await
+        {
+            Ok(res_value) => {
+                let model = stock_map
+                    .entry(res_value.0.clone())
+                    .or_insert_with(|| Concentration {
+                        stock_id: res_value.0,
+                        exchange_date: formatted_date.clone(),
+                        concentration: vec![0; 5],
+                        sum_buy_shares: 0,
+                        sum_sell_shares: 0,
+                        avg_buy_price: 0.0,
+                        avg_sell_price: 0.0,
+                        current: 0,
+                    });
+                model.concentration[res_value.1] = res_value.2; // Set diff num based on index
+
+                if res_value.1 == 0 {
+                    model.sum_buy_shares = res_value.3;
+                    model.sum_sell_shares = res_value.4;
+                    model.avg_buy_price = res_value.5;
+                    model.avg_sell_price = res_value.6;
+                }
+
+                model.current += 1;
+                if model.current == CONCENTRATION_PAGES {
+                    let payload = model.to_json().unwrap();
+                    match &kproducer
+                        .send("stakeconcentration-v1".to_string(), payload.clone())
+                        .await
+                    {
+                        Ok(_) => println!("{}", payload),
+                        Err(e) => eprintln!("Failed to send message: {}", e),
+                    }
+                }
+            }
+            Err(e) if super::abort_on_drift(&e) => break,
+            Err(e) => {
+                eprintln!("Failed to parse content for URL {}: {}", url, e);
+                continue;
             }
+        }
 
-            model.current += 1;
-            if model.current == 5 {
-                let payload = model.to_json().unwrap();
-                match &kproducer
-                    .send("stakeconcentration-v1".to_string(), payload.clone())
-                    .await
-                {
-                    Ok(_) => println!("{}", payload),
-                    Err(e) => eprintln!("Failed to send message: {}", e),
+        // published once the concentration of the page was parsed
+        match Parser::new(BrokerStrategy).parse(payload).await {
+            Ok(brokers) => {
+                for broker in brokers {
+                    let payload = broker.to_json().unwrap();
+                    match &kproducer
+                        .send("brokerflow-v1".to_string(), payload.clone())
+                        .await
+                    {
+                        Ok(_) => println!("{}", payload),
+                        Err(e) => eprintln!("Failed to send message: {}", e),
+                    }
                 }
             }
-        } else if let Err(e) = res {
-            eprintln!("Failed to parse content for URL {}: {}", url, e);
+            Err(e) if super::abort_on_drift(&e) => break,
+            Err(e) => eprintln!("Failed to parse brokers for URL {}: {}", url, e),
         }
     }
+
+    // stocks missing a page are never published
+    let mut incomplete: Vec<_> = stock_map
+        .values()
+        .filter(|model| model.current < CONCENTRATION_PAGES)
+        .map(|model| {
+            format!(
+                "{} ({}/{} pages)",
+                model.stock_id, model.current, CONCENTRATION_PAGES
+            )
+        })
+        .collect();
+    if !incomplete.is_empty() {
+        incomplete.sort();
+        eprintln!("Concentration not published for {}", incomplete.join(", "));
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::engine::fetcher::cassette::Cassette;
+    use crate::process::kafka::sent_messages;
+    use chrono::TimeZone;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_execute_with_cassette() {
+        let _cassette = Cassette::insert(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/cassettes/synthetic/concentration_2330_20240723.json"
+        ))
+        .unwrap();
+        let date = Local.with_ymd_and_hms(2024, 7, 23, 0, 0, 0).unwrap();
+
+        execute(date, vec!["2330".to_string()]).await;
+
+        let messages = sent_messages("stakeconcentration-v1");
+        assert_eq!(
+            messages,
+            vec![
+                r#"{"stockId":"2330","exchangeDate":"20240723","diff":[856,1000,-1000,2000,5000],"sumBuyShares":2108,"sumSellShares":1252,"avgBuyPrice":54.59,"avgSellPrice":54.32}"#
+            ]
+        );
+
+        // a buying and a selling branch on each of the five pages
+        let brokers = sent_messages("brokerflow-v1");
+        assert_eq!(brokers.len(), 10);
+        assert!(brokers.contains(
+            &r#"{"stockId":"2330","exchangeDate":"20240723","period":0,"side":"buy","rank":1,"brokerId":"5380","branchId":"538E","brokerName":"第一金-自由","buyShares":34,"sellShares":8,"netShares":26,"volumeRatio":0.32}"#.to_string()
+        ));
+    }
 }
@@ -0,0 +1,78 @@
+use crate::config::setting::SETTINGS;
+use crate::engine::dataset::Dataset;
+use crate::engine::fetcher::{FetchRequest, Payload, PayloadStream};
+use crate::engine::parser::{Parsed, Parser};
+use crate::engine::strategies::dataset::{DatasetStrategy, Record};
+use crate::process::pipeline::{self, Target};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use std::path::Path;
+use std::sync::Arc;
+
+struct DatasetTarget {
+    dataset: Arc<Dataset>,
+}
+
+pub async fn execute(date: DateTime<Local>, dataset: Dataset) {
+    pipeline::execute(date, DatasetTarget::new(dataset)).await
+}
+
+/// Run the aggregation on previously archived payloads instead of live HTTP
+pub async fn replay(date: DateTime<Local>, dataset: Dataset, payloads: Vec<Payload>) {
+    pipeline::replay(date, DatasetTarget::new(dataset), payloads).await
+}
+
+/// Spec of the declarative dataset named `target`, logging why none is usable
+pub fn find(target: &str) -> Option<Dataset> {
+    match Dataset::find(Path::new(&SETTINGS.datasets.path), target) {
+        Ok(Some(dataset)) => Some(dataset),
+        Ok(None) => {
+            eprintln!("Unknown target: {}", target);
+            None
+        }
+        Err(e) => {
+            eprintln!("Failed to load dataset {}: {}", target, e);
+            None
+        }
+    }
+}
+
+impl DatasetTarget {
+    fn new(dataset: Dataset) -> Self {
+        Self {
+            dataset: Arc::new(dataset),
+        }
+    }
+}
+
+#[async_trait]
+impl Target for DatasetTarget {
+    type Record = Record;
+
+    fn name(&self) -> &str {
+        &self.dataset.name
+    }
+
+    fn topic(&self) -> &str {
+        &self.dataset.topic
+    }
+
+    fn requests(&self, date: DateTime<Local>) -> Vec<FetchRequest> {
+        self.dataset
+            .sources
+            .iter()
+            .map(|source| source.request(date))
+            .collect()
+    }
+
+    async fn parse(&self, payload: PayloadStream) -> anyhow::Result<Parsed<Record>> {
+        Parser::new(DatasetStrategy::new(self.dataset.clone()))
+            .parse(payload)
+            .await
+    }
+
+    fn to_json(record: &Record) -> Result<String, serde_json::Error> {
+        serde_json::to_string(record)
+    }
+}
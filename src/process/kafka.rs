@@ -1,27 +1,63 @@
 use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
 use rdkafka::config::ClientConfig;
 use rdkafka::producer::{FutureProducer, FutureRecord};
+use std::sync::Mutex;
 use std::time::Duration;
 use tokio::time::sleep;
 
+use crate::config::setting::{RetryPolicy, SETTINGS};
+
+lazy_static! {
+    // Messages are captured here instead of delivered when built for testing
+    static ref SENT: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+}
+
+/// Messages sent to a topic so far, only available when built for testing
+#[cfg(all(test, feature = "testing"))]
+pub fn sent_messages(topic: &str) -> Vec<String> {
+    SENT.lock()
+        .expect("Sent messages lock poisoned")
+        .iter()
+        .filter(|(t, _)| t == topic)
+        .map(|(_, message)| message.clone())
+        .collect()
+}
+
 pub struct Producer {
     producer: FutureProducer,
+    policy: RetryPolicy,
 }
 
 impl Producer {
     pub fn new(brokers: &str) -> Result<Self> {
         println!("Creating producer with brokers: {}", brokers);
+        let policy = SETTINGS.retry.for_sink("kafka");
         let producer: FutureProducer = ClientConfig::new()
             .set("bootstrap.servers", brokers)
-            .set("message.timeout.ms", "5000")
+            .set(
+                "message.timeout.ms",
+                (policy.timeout_secs * 1000).to_string(),
+            )
+            .set(
+                "socket.connection.setup.timeout.ms",
+                (policy.connect_timeout_secs * 1000).to_string(),
+            )
             .create()
             .map_err(|e| anyhow!("Producer creation error: {}", e))?;
-        Ok(Producer { producer })
+        Ok(Producer { producer, policy })
     }
 
     pub async fn send(&self, topic: String, message: String) -> Result<(), anyhow::Error> {
+        if cfg!(feature = "testing") {
+            SENT.lock()
+                .expect("Sent messages lock poisoned")
+                .push((topic, message));
+            return Ok(());
+        }
+
         let mut attempts = 0;
-        let max_attempts = 3;
+        let max_attempts = self.policy.max_retries + 1;
 
         loop {
             let record: FutureRecord<String, String> = FutureRecord::to(&topic).payload(&message);
@@ -41,7 +77,7 @@ impl Producer {
                         "Failed to send message: {}. Retrying... (attempt {}/{})",
                         kafka_error, attempts, max_attempts
                     );
-                    sleep(Duration::from_secs(1)).await;
+                    sleep(self.policy.backoff(attempts)).await;
                 }
             }
         }
@@ -1,4 +1,29 @@
 pub mod concentration;
 pub mod daily_close;
+pub mod dataset;
 mod kafka;
+mod pipeline;
 pub mod three_primary;
+
+use crate::engine::schema::SchemaDrift;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a parse error means the upstream layout changed, in which case the
+/// run is marked failed and the caller stops emitting records
+pub fn abort_on_drift(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<SchemaDrift>() {
+        Some(drift) => {
+            eprintln!("Aborting run: {}", drift);
+            FAILED.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Whether a process aborted, so the run exits with a failure status
+pub fn failed() -> bool {
+    FAILED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,177 @@
+use crate::config::setting::SETTINGS;
+use crate::engine::fetcher::{
+    fetch_stream_and_archive, FetchError, FetchRequest, Payload, PayloadStream,
+};
+use crate::engine::parser::Parsed;
+use crate::process::{self, kafka::Producer};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore};
+
+static CAPACITY: usize = 2;
+
+/// A target fetched from its sources of the day, parsed and published to Kafka
+#[async_trait]
+pub trait Target: Send + Sync + 'static {
+    type Record: Send;
+
+    /// Name of the target, also its archive directory
+    fn name(&self) -> &str;
+
+    /// Kafka topic the records are published to
+    fn topic(&self) -> &str;
+
+    /// Requests of the sources publishing the target on `date`
+    fn requests(&self, date: DateTime<Local>) -> Vec<FetchRequest>;
+
+    /// Parse a payload fetched from one of the sources
+    async fn parse(&self, payload: PayloadStream) -> anyhow::Result<Parsed<Self::Record>>;
+
+    /// Kafka message of a record
+    fn to_json(record: &Self::Record) -> Result<String, serde_json::Error>;
+}
+
+pub async fn execute<T: Target>(date: DateTime<Local>, target: T) {
+    let target = Arc::new(target);
+    let (url_tx, url_rx) = mpsc::channel(CAPACITY);
+
+    // retrieve all handles and ensure process not termiated before tasks completed
+    let url_gen_handle = tokio::spawn(generate_requests(date, target.clone(), url_tx));
+    let fetch_aggregate_handle = tokio::spawn(fetch_requests(date, target, url_rx, CAPACITY));
+
+    // Await on both handles to ensure completion
+    let _results = tokio::try_join!(url_gen_handle, fetch_aggregate_handle);
+}
+
+/// Run the aggregation on previously archived payloads instead of live HTTP
+pub async fn replay<T: Target>(date: DateTime<Local>, target: T, payloads: Vec<Payload>) {
+    let (content_tx, content_rx) = mpsc::channel(payloads.len().max(1));
+    for payload in payloads {
+        content_tx
+            .send(PayloadStream::from(payload))
+            .await
+            .expect("Failed to send payload");
+    }
+    drop(content_tx);
+
+    aggregate(date, Arc::new(target), content_rx).await;
+}
+
+fn get_date(day: DateTime<Local>) -> String {
+    day.format("%Y%m%d").to_string()
+}
+
+async fn generate_requests<T: Target>(
+    date: DateTime<Local>,
+    target: Arc<T>,
+    url_tx: mpsc::Sender<FetchRequest>,
+) {
+    for request in target.requests(date) {
+        url_tx.send(request).await.expect("Failed to send URL");
+    }
+
+    drop(url_tx);
+}
+
+async fn fetch_requests<T: Target>(
+    date: DateTime<Local>,
+    target: Arc<T>,
+    mut url_rx: mpsc::Receiver<FetchRequest>,
+    capacity: usize,
+) {
+    let semaphore = Arc::new(Semaphore::new(2));
+    let (content_tx, content_rx) = mpsc::channel(capacity);
+    let name = target.name().to_string();
+
+    let fetch_handle = tokio::spawn(async move {
+        while let Some(request) = url_rx.recv().await {
+            let sem_clone = Arc::clone(&semaphore);
+            let content_tx_clone = content_tx.clone();
+            let name = name.clone();
+            tokio::spawn(async move {
+                let _permit = sem_clone
+                    .acquire()
+                    .await
+                    .expect("Failed to acquire semaphore permit");
+
+                println!("Fetching data from {}", request.url);
+                if let Some(payload) = fetch(request, &name, &get_date(date)).await {
+                    if let Err(e) = content_tx_clone.send(payload).await {
+                        eprintln!("Failed to send content: {}", e);
+                    }
+                }
+            });
+        }
+    });
+
+    let aggregate_handle = tokio::spawn(aggregate(date, target, content_rx));
+    // Await on both handles to ensure completion
+    let _results = tokio::try_join!(fetch_handle, aggregate_handle);
+}
+
+/// Fetch `request`, archived under `target` and `date`, `None` when it failed.
+/// Failures were already retried following the retry policy of the host.
+async fn fetch(
+    request: impl Into<FetchRequest>,
+    target: &str,
+    date: &str,
+) -> Option<PayloadStream> {
+    match fetch_stream_and_archive(request, target, date).await {
+        Ok(payload) => Some(payload),
+        // nothing published, e.g. a market holiday
+        Err(FetchError::NotFound(source)) => {
+            println!("No data published at {}, skipped", source);
+            None
+        }
+        Err(e) => {
+            eprintln!("Failed to fetch payload: {}", e);
+            None
+        }
+    }
+}
+
+async fn aggregate<T: Target>(
+    date: DateTime<Local>,
+    target: Arc<T>,
+    mut content_rx: mpsc::Receiver<PayloadStream>,
+) {
+    // Create a new producer using match to handle the Result
+    let kproducer = match Producer::new(&SETTINGS.kafka.connection_string()) {
+        Ok(kproducer) => kproducer,
+        Err(e) => {
+            eprintln!("Failed to create producer: {}", e);
+            return;
+        }
+    };
+
+    while let Some(mut raw_payload) = content_rx.recv().await {
+        let source = raw_payload.source.clone();
+        raw_payload.date = Some(get_date(date));
+        match target.parse(raw_payload).await {
+            Ok(Parsed { records, report }) => {
+                println!("Parsed {}: {}", source, report);
+
+                for record in records {
+                    match T::to_json(&record) {
+                        Ok(payload) => {
+                            match &kproducer
+                                .send(target.topic().to_string(), payload.clone())
+                                .await
+                            {
+                                Ok(_) => println!("{}", payload),
+                                Err(e) => eprintln!("Failed to send message: {}", e),
+                            }
+                        }
+                        Err(e) => eprintln!("Failed to convert record to JSON: {}", e),
+                    }
+                }
+            }
+            Err(e) if process::abort_on_drift(&e) => break,
+            Err(e) => {
+                eprintln!("Failed to parse payload: {}", e);
+            }
+        }
+    }
+}
@@ -1,24 +1,23 @@
-use crate::config::setting::SETTINGS;
-use crate::engine::fetcher::{fetch_content, Payload};
-use crate::engine::parser::Parser;
-use crate::engine::strategies::three_primary::ThreePrimaryStrategy;
-use crate::process::kafka::Producer;
-
+use crate::config::setting::{SourceFormat, SETTINGS};
+use crate::engine::fetcher::{FetchRequest, Payload, PayloadStream};
+use crate::engine::models::three_primary::ThreePrimary;
+use crate::engine::parser::{Parsed, Parser};
+use crate::engine::strategies::rwd;
+use crate::engine::strategies::three_primary::{ThreePrimaryJsonStrategy, ThreePrimaryStrategy};
+use crate::process::pipeline::{self, Target};
+
+use async_trait::async_trait;
 use chrono::{DateTime, Datelike, Local};
-use std::sync::Arc;
-use tokio::sync::{mpsc, Semaphore};
 
-static CAPACITY: usize = 2;
+struct ThreePrimaryTarget;
 
 pub async fn execute(date: DateTime<Local>) {
-    let (url_tx, url_rx) = mpsc::channel(CAPACITY);
-
-    // retrieve all handles and ensure process not termiated before tasks completed
-    let url_gen_handle = tokio::spawn(generate_urls(date, url_tx));
-    let fetch_aggregate_handle = tokio::spawn(fetch_urls(date, url_rx, CAPACITY));
+    pipeline::execute(date, ThreePrimaryTarget).await
+}
 
-    // Await on both handles to ensure completion
-    let _results = tokio::try_join!(url_gen_handle, fetch_aggregate_handle);
+/// Run the aggregation on previously archived payloads instead of live HTTP
+pub async fn replay(date: DateTime<Local>, payloads: Vec<Payload>) {
+    pipeline::replay(date, ThreePrimaryTarget, payloads).await
 }
 
 fn get_date(day: DateTime<Local>, exchange_type: &str) -> String {
@@ -29,92 +28,76 @@ fn get_date(day: DateTime<Local>, exchange_type: &str) -> String {
     }
 }
 
-async fn generate_urls(date: DateTime<Local>, url_tx: mpsc::Sender<String>) {
-    let twse_url = format!(
-        "https://www.twse.com.tw/rwd/zh/fund/T86?response=csv&date={}&selectType=ALLBUT0999",
-        get_date(date, "twse")
-    );
-
-    let tpex_url = format!(
-        "https://www.tpex.org.tw/web/stock/3insti/daily_trade/3itrade_hedge_result.php?l=zh-tw&o=csv&se=EW&t=D&d={}",
-        get_date(date, "tpex")
-    );
-
-    let urls: [&str; 2] = [&twse_url, &tpex_url];
-    for url in urls {
-        url_tx
-            .send(url.to_string())
-            .await
-            .expect("Failed to send URL");
+#[async_trait]
+impl Target for ThreePrimaryTarget {
+    type Record = ThreePrimary;
+
+    fn name(&self) -> &str {
+        "three_primary"
     }
 
-    drop(url_tx);
-}
+    fn topic(&self) -> &str {
+        "threeprimary-v1"
+    }
 
-async fn fetch_urls(date: DateTime<Local>, mut url_rx: mpsc::Receiver<String>, capacity: usize) {
-    let semaphore = Arc::new(Semaphore::new(2));
-    let (content_tx, content_rx) = mpsc::channel(capacity);
-
-    let fetch_handle = tokio::spawn(async move {
-        while let Some(url) = url_rx.recv().await {
-            let sem_clone = Arc::clone(&semaphore);
-            let content_tx_clone = content_tx.clone();
-            tokio::spawn(async move {
-                let _permit = sem_clone
-                    .acquire()
-                    .await
-                    .expect("Failed to acquire semaphore permit");
-
-                println!("Fetching data from {}", url);
-                match fetch_content(url).await {
-                    Ok(payload) => {
-                        if let Err(e) = content_tx_clone.send(payload).await {
-                            eprintln!("Failed to send content: {}", e);
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to fetch payload: {}", e);
-                    }
-                }
-            });
+    fn requests(&self, date: DateTime<Local>) -> Vec<FetchRequest> {
+        let twse_url = match SETTINGS.sources.twse_format("three_primary") {
+            SourceFormat::Csv => format!(
+                "https://www.twse.com.tw/rwd/zh/fund/T86?response=csv&date={}&selectType=ALLBUT0999",
+                get_date(date, "twse")
+            ),
+            SourceFormat::Json => format!(
+                "https://www.twse.com.tw/rwd/zh/fund/T86?response=json&date={}&selectType=ALLBUT0999",
+                get_date(date, "twse")
+            ),
+        };
+
+        let tpex_url = format!(
+            "https://www.tpex.org.tw/web/stock/3insti/daily_trade/3itrade_hedge_result.php?l=zh-tw&o=csv&se=EW&t=D&d={}",
+            get_date(date, "tpex")
+        );
+
+        vec![twse_url.into(), tpex_url.into()]
+    }
+
+    async fn parse(&self, payload: PayloadStream) -> anyhow::Result<Parsed<ThreePrimary>> {
+        if rwd::is_json(&payload.source) {
+            Parser::new(ThreePrimaryJsonStrategy).parse(payload).await
+        } else {
+            Parser::new(ThreePrimaryStrategy).parse(payload).await
         }
-    });
+    }
 
-    let aggregate_handle = tokio::spawn(aggregate(date, content_rx));
-    // Await on both handles to ensure completion
-    let _results = tokio::try_join!(fetch_handle, aggregate_handle);
+    fn to_json(record: &ThreePrimary) -> Result<String, serde_json::Error> {
+        record.to_json()
+    }
 }
 
-async fn aggregate(date: DateTime<Local>, mut content_rx: mpsc::Receiver<Payload>) {
-    // Create a new producer using match to handle the Result
-    let kproducer = match Producer::new(&SETTINGS.kafka.connection_string()) {
-        Ok(kproducer) => kproducer,
-        Err(e) => {
-            eprintln!("Failed to create producer: {}", e);
-            return;
-        }
-    };
-
-    while let Some(raw) = content_rx.recv().await {
-        let mut raw_payload = raw.clone();
-        raw_payload.date = Some(get_date(date, "twse"));
-        let parser = Parser::new(ThreePrimaryStrategy);
-        match parser.parse(raw_payload).await {
-            Ok(result) => {
-                for record in result {
-                    let payload = record.to_json().unwrap();
-                    match &kproducer
-                        .send("threeprimary-v1".to_string(), payload.clone())
-                        .await
-                    {
-                        Ok(_) => println!("{}", payload),
-                        Err(e) => eprintln!("Failed to send message: {}", e),
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("Failed to parse payload: {}", e);
-            }
-        }
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::engine::fetcher::cassette::Cassette;
+    use crate::process::kafka::sent_messages;
+    use chrono::TimeZone;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_execute_with_cassette() {
+        let _cassette = Cassette::insert(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/cassettes/synthetic/three_primary_20240723.json"
+        ))
+        .unwrap();
+        let date = Local.with_ymd_and_hms(2024, 7, 23, 0, 0, 0).unwrap();
+
+        execute(date).await;
+
+        let messages = sent_messages("threeprimary-v1");
+        assert_eq!(messages.len(), 3);
+        assert!(messages.contains(
+            &r#"{"stockId":"2330","date":"20240723","foreignTradeShares":-5000000,"trustTradeShares":400000,"dealerTradeShares":150000,"hedgingTradeShares":150000}"#.to_string()
+        ));
+        assert!(messages.contains(
+            &r#"{"stockId":"6488","date":"20240723","foreignTradeShares":120000,"trustTradeShares":-3000,"dealerTradeShares":5000,"hedgingTradeShares":-1000}"#.to_string()
+        ));
     }
 }